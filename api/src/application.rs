@@ -1,8 +1,15 @@
-use k8s_openapi::api::core::v1::ResourceRequirements;
+use anyhow::{bail, Context, Result};
+use k8s_openapi::api::core::v1::{
+    Affinity, ConfigMapKeySelector, EnvVarSource, ObjectFieldSelector, ResourceFieldSelector,
+    ResourceRequirements, SecretKeySelector, Toleration,
+};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
 pub mod v1 {
@@ -21,6 +28,13 @@ pub mod v1 {
     )]
     #[serde(rename_all = "camelCase")]
     pub struct ApplicationSpec {
+        /// Restrict network traffic to and from the application.
+        /// When set, only the listed inbound traffic is allowed, in addition to traffic from the
+        /// ingress controllers of the zones the application is exposed in.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub access_policy: Option<AccessPolicy>,
+
         /// The environment variables to set in the container.
         #[serde(default)]
         #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -32,8 +46,21 @@ pub mod v1 {
         #[serde(skip_serializing_if = "Vec::is_empty")]
         pub env_from: Vec<EnvFrom>,
 
+        /// Files to mount in the container, from file name to file contents.
+        /// The files are placed in a configmap owned by the application, and a change to the
+        /// contents rolls out the application.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub files: BTreeMap<String, String>,
+
+        /// The path to mount `files` to.
+        /// The default value is /var/run/config/yakup.ibidem.no/<application name>-files.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub files_mount_path: Option<String>,
+
         /// Mount files from the listed sources.
-        /// A source can be either a configmap, a secret, or an emptyDir.
+        /// A source can be either a configmap, a secret, an emptyDir or a persistentVolume.
         #[serde(default)]
         #[serde(skip_serializing_if = "Vec::is_empty")]
         pub files_from: Vec<FilesFrom>,
@@ -41,6 +68,57 @@ pub mod v1 {
         /// The image to run.
         pub image: String,
 
+        /// When the kubelet pulls the image.
+        /// Defaults to Always for images tagged latest, otherwise IfNotPresent.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub image_pull_policy: Option<ImagePullPolicy>,
+
+        /// Names of secrets with credentials for pulling the image,
+        /// in addition to the default pull secrets in the controller configuration.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub image_pull_secrets: Vec<String>,
+
+        /// Resolve the tag in `spec.image` to a digest through the registry API,
+        /// and deploy the image by digest. Overrides `pinImageDigests` in the controller configuration.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub pin_image_digest: Option<bool>,
+
+        /// Get configuration from the implicit configmaps and secrets named after the application.
+        /// Set to false if the application doesn't use them. Defaults to true.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub implicit_sources: Option<bool>,
+
+        /// Names of implicit configmaps and secrets that should not restart the application when
+        /// their contents change, such as `myapp-db`. They are still used as configuration.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub implicit_sources_without_rollout: Vec<String>,
+
+        /// Access to the Kubernetes API in the application namespace, granted to the ServiceAccount
+        /// of the application through a generated Role and RoleBinding.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub kubernetes_access: Vec<KubernetesAccess>,
+
+        /// Annotations on the pods of the application, such as settings for a service mesh.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub pod_annotations: BTreeMap<String, String>,
+
+        /// Labels on the pods of the application.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub pod_labels: BTreeMap<String, String>,
+
+        /// Override the PodDisruptionBudget generated for applications with more than one replica.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub pod_disruption_budget: Option<PodDisruptionBudget>,
+
         #[serde(default)]
         #[serde(skip_serializing_if = "Option::is_none")]
         pub ports: Option<Ports>,
@@ -54,6 +132,27 @@ pub mod v1 {
         #[serde(default)]
         #[serde(skip_serializing_if = "Option::is_none")]
         pub resources: Option<ResourceRequirements>,
+
+        /// Control how new versions of the application are rolled out, and how pods are stopped.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub rollout: Option<Rollout>,
+
+        /// Control how the application is exposed by its Service.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub service: Option<ServiceOptions>,
+
+        /// Customize the ServiceAccount the application runs as.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub service_account: Option<ServiceAccountOptions>,
+
+        /// Control where the pods of this application are scheduled.
+        /// Values set here override the defaults from the controller configuration.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub scheduling: Option<Scheduling>,
         //   replicas:
         //     min: 1
         //     max: 5
@@ -75,27 +174,219 @@ pub mod v1 {
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ApplicationStatus {
+    #[serde(default)]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub conditions: Vec<Condition>,
+
+    /// The image deployed when the tag in `spec.image` is pinned to a digest.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_image: Option<String>,
+
+    /// The generation of the application the image was resolved for.
+    /// The tag is resolved again when the application changes.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_image_generation: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessPolicy {
+    /// Allow traffic to the application from these peers.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inbound: Vec<AccessRule>,
+
+    /// Allow traffic from the application to these peers.
+    /// If empty, outbound traffic is not restricted.
+    /// If not empty, DNS lookups are allowed in addition to the listed peers.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outbound: Vec<AccessRule>,
+}
+
+/// A peer is either an application, all applications in a namespace, or a CIDR.
+/// It is an error to combine a CIDR with application or namespace in one rule.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessRule {
+    /// The name of an application.
+    /// If not set, all pods in the namespace are allowed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub application: Option<String>,
+
+    /// The namespace of the application.
+    /// Defaults to the namespace of this application.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// An IP block, in CIDR notation, outside the cluster.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cidr: Option<String>,
+
+    /// IP blocks, in CIDR notation, to exclude from `cidr`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub except: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct EnvValue {
     pub name: String,
-    pub value: String,
+
+    /// A literal value for the environment variable.
+    /// Exactly one of `value` and `valueFrom` must be set.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+
+    /// Get the value of the environment variable from another source.
+    /// Exactly one of `value` and `valueFrom` must be set.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value_from: Option<EnvValueFrom>,
 }
 
 impl EnvValue {
-    pub fn to_kube(&self) -> k8s_openapi::api::core::v1::EnvVar {
-        k8s_openapi::api::core::v1::EnvVar {
+    pub fn to_kube(&self) -> Result<k8s_openapi::api::core::v1::EnvVar> {
+        let value_from = match (&self.value, &self.value_from) {
+            (Some(_), None) => None,
+            (None, Some(value_from)) => Some(
+                value_from
+                    .to_kube()
+                    .with_context(|| format!("invalid valueFrom for env {}", self.name))?,
+            ),
+            _ => bail!(
+                "exactly one of value and valueFrom must be set for env {}",
+                self.name
+            ),
+        };
+        Ok(k8s_openapi::api::core::v1::EnvVar {
             name: self.name.clone(),
-            value: Some(self.value.clone()),
-            value_from: None,
+            value: self.value.clone(),
+            value_from,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvValueFrom {
+    /// Select a key from a secret in the application namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key_ref: Option<KeyRef>,
+
+    /// Select a key from a configmap in the application namespace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_map_key_ref: Option<KeyRef>,
+
+    /// Select a field of the pod using the downward API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field_ref: Option<FieldRef>,
+
+    /// Select a resource limit or request of the container using the downward API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_field_ref: Option<ResourceFieldRef>,
+}
+
+impl EnvValueFrom {
+    pub fn to_kube(&self) -> Result<EnvVarSource> {
+        let mut source = EnvVarSource::default();
+        let mut count = 0;
+        if let Some(key_ref) = &self.secret_key_ref {
+            source.secret_key_ref = Some(SecretKeySelector {
+                name: key_ref.name.clone(),
+                key: key_ref.key.clone(),
+                optional: key_ref.optional,
+            });
+            count += 1;
+        }
+        if let Some(key_ref) = &self.config_map_key_ref {
+            source.config_map_key_ref = Some(ConfigMapKeySelector {
+                name: key_ref.name.clone(),
+                key: key_ref.key.clone(),
+                optional: key_ref.optional,
+            });
+            count += 1;
         }
+        if let Some(field_ref) = &self.field_ref {
+            source.field_ref = Some(ObjectFieldSelector {
+                api_version: None,
+                field_path: field_ref.field_path().to_string(),
+            });
+            count += 1;
+        }
+        if let Some(resource_field_ref) = &self.resource_field_ref {
+            source.resource_field_ref = Some(ResourceFieldSelector {
+                container_name: None,
+                divisor: resource_field_ref.divisor.clone(),
+                resource: resource_field_ref.resource.clone(),
+            });
+            count += 1;
+        }
+        if count != 1 {
+            bail!(
+                "exactly one source must be set in valueFrom, found {}",
+                count
+            );
+        }
+        Ok(source)
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyRef {
+    /// The name of the secret or configmap.
+    pub name: String,
+
+    /// The key to select.
+    pub key: String,
+
+    /// Allow the secret or configmap, or the key, to be missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional: Option<bool>,
+
+    /// Restart the application when the contents of this source change.
+    /// Defaults to true.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout_on_change: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+pub enum FieldRef {
+    #[default]
+    PodName,
+    Namespace,
+    NodeName,
+    PodIP,
+}
+
+impl FieldRef {
+    pub fn field_path(&self) -> &'static str {
+        match self {
+            FieldRef::PodName => "metadata.name",
+            FieldRef::Namespace => "metadata.namespace",
+            FieldRef::NodeName => "spec.nodeName",
+            FieldRef::PodIP => "status.podIP",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceFieldRef {
+    /// The resource to select, for example `limits.cpu` or `requests.memory`.
+    pub resource: String,
+
+    /// Specifies the output format of the exposed resources, defaults to "1".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub divisor: Option<Quantity>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct EnvFrom {
@@ -108,6 +399,12 @@ pub struct EnvFrom {
     /// Keys that are not valid environment variable names will be skipped.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub secret: Option<String>,
+
+    /// Restart the application when the contents of this source change.
+    /// Defaults to true.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout_on_change: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
@@ -119,6 +416,8 @@ pub struct FilesFrom {
     pub secret: Option<FilesFromSecret>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub empty_dir: Option<FilesFromEmptyDir>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persistent_volume: Option<FilesFromPersistentVolume>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
@@ -130,6 +429,34 @@ pub struct FilesFromConfigMap {
     /// The default value is /var/run/config/yakup.ibidem.no/<name>.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mount_path: Option<String>,
+
+    /// Restart the application when the contents of this source change.
+    /// Defaults to true.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout_on_change: Option<bool>,
+
+    /// Only project the listed keys, at the given paths relative to the mount path.
+    /// If not set, all keys are projected using the key as file name.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<FilesFromItem>,
+
+    /// Mode bits used for the projected files, unless overridden per item.
+    /// Defaults to 0644.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_mode: Option<i32>,
+
+    /// Mount only this path from the configmap, instead of the whole configmap.
+    /// The mount path is then the path of the mounted file, which makes it possible to place a
+    /// single file in an existing directory without shadowing the rest of the directory.
+    /// The default mount path is <default mount path>/<subPath>.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_path: Option<String>,
+
+    /// Allow the configmap, or the keys listed in `items`, to be missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
@@ -141,6 +468,58 @@ pub struct FilesFromSecret {
     /// The default value is /var/run/secrets/yakup.ibidem.no/<name>.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mount_path: Option<String>,
+
+    /// Restart the application when the contents of this source change.
+    /// Defaults to true.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rollout_on_change: Option<bool>,
+
+    /// Only project the listed keys, at the given paths relative to the mount path.
+    /// If not set, all keys are projected using the key as file name.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<FilesFromItem>,
+
+    /// Mode bits used for the projected files, unless overridden per item.
+    /// Defaults to 0644.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_mode: Option<i32>,
+
+    /// Mount only this path from the secret, instead of the whole secret.
+    /// The mount path is then the path of the mounted file, which makes it possible to place a
+    /// single file in an existing directory without shadowing the rest of the directory.
+    /// The default mount path is <default mount path>/<subPath>.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_path: Option<String>,
+
+    /// Allow the secret, or the keys listed in `items`, to be missing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesFromItem {
+    /// The key to project.
+    pub key: String,
+
+    /// The relative path of the file to map the key to.
+    pub path: String,
+
+    /// Mode bits used for this file. Defaults to `defaultMode`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<i32>,
+}
+
+impl FilesFromItem {
+    pub fn to_kube(&self) -> k8s_openapi::api::core::v1::KeyToPath {
+        k8s_openapi::api::core::v1::KeyToPath {
+            key: self.key.clone(),
+            path: self.path.clone(),
+            mode: self.mode,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
@@ -151,6 +530,50 @@ pub struct FilesFromEmptyDir {
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesFromPersistentVolume {
+    /// The name of the volume.
+    /// Shared volumes are backed by a PersistentVolumeClaim named <application name>-<name>.
+    pub name: String,
+
+    /// The path to mount the volume to.
+    pub mount_path: String,
+
+    /// The requested size of the volume.
+    pub size: Quantity,
+
+    /// StorageClass to use for the volume.
+    /// If not set, the default class will be used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_class: Option<String>,
+
+    /// How the volume can be mounted. Defaults to ReadWriteOnce.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_mode: Option<AccessMode>,
+
+    /// Give each replica a volume of its own, instead of sharing one volume between all replicas.
+    /// Applications with per-replica volumes are deployed as a StatefulSet instead of a Deployment.
+    /// The volume settings can not be changed once a StatefulSet has been created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_replica: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+pub enum AccessMode {
+    #[default]
+    ReadWriteOnce,
+    ReadOnlyMany,
+    ReadWriteMany,
+    ReadWriteOncePod,
+}
+
+impl Display for AccessMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema, PartialEq)]
 pub enum PathType {
     #[default]
     Prefix,
@@ -175,6 +598,128 @@ pub struct Ingress {
     #[serde(default = "default_paths")]
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub paths: Vec<String>,
+
+    /// Extra hostnames in this zone, in addition to the host generated from the zone.
+    /// Each hostname must be within one of the `allowedDomains` of the zone.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+
+    /// Annotations on the Ingress or HTTPRoute, overriding the annotations of the zone.
+    /// Only the keys in `allowedAnnotations` of the zone may be set.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+
+    /// Send requests for some paths to another service port, by path.
+    /// The port is `http` or `tcp`. Paths not listed here go to the `http` port.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub path_ports: BTreeMap<String, String>,
+
+    /// Replace the matched path prefix with this prefix before passing requests to the application.
+    /// Only valid with pathType Prefix.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewrite_prefix: Option<String>,
+
+    /// Redirect HTTP requests to HTTPS.
+    /// If not set, the default of the ingress controller or gateway is used.
+    /// In Gateway zones only `true` is supported, and the zone must set both `sectionName` and
+    /// `httpSectionName`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub https_redirect: Option<bool>,
+
+    /// Old hostnames that are permanently redirected to the host of the application in this zone.
+    /// Each hostname must be within one of the `allowedDomains` of the zone.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redirect_from: Vec<String>,
+
+    /// Require users to authenticate with the auth provider of the zone.
+    /// Defaults to the `defaultMode` of the zone.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth: Option<AuthMode>,
+
+    /// Common ingress settings, mapped to annotations for the ingress controller of the zone.
+    /// The annotations must be in the `allowedAnnotations` of the zone.
+    /// Not supported in zones using a Gateway.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<IngressOptions>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AuthMode {
+    /// Unauthenticated users are redirected to sign in.
+    Required,
+    /// Unauthenticated users are let through, authenticated users are identified to the application.
+    Optional,
+    /// No authentication.
+    #[default]
+    None,
+}
+
+impl Display for AuthMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressOptions {
+    /// The maximum size of a request body, such as `8m`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_body_size: Option<String>,
+
+    /// Timeout for reading from and writing to the application.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u32>,
+
+    /// Only allow requests from these source ranges, in CIDR notation.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_source_ranges: Vec<String>,
+
+    /// Enable CORS for the application.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cors: Option<Cors>,
+
+    /// Rewrite the path of matching requests to this target before passing them to the application.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewrite_target: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Cors {
+    /// Origins allowed to make cross-origin requests. Defaults to all origins.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_origins: Vec<String>,
+
+    /// Methods allowed in cross-origin requests.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_methods: Vec<String>,
+
+    /// Headers allowed in cross-origin requests.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_headers: Vec<String>,
+
+    /// Allow credentials in cross-origin requests.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub allow_credentials: Option<bool>,
 }
 
 fn default_paths() -> Vec<String> {
@@ -195,6 +740,10 @@ pub struct Ports {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tcp: Option<TcpPort>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub udp: Option<UdpPort>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
@@ -203,6 +752,11 @@ pub struct HttpPort {
     /// Container port to expose.
     pub port: u16,
 
+    /// The port of the Service. Defaults to 80.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_port: Option<u16>,
+
     /// If this port should be exposed as an ingress.
     /// `ingress` is only valid on ports of kind PortKind::HTTP.
     #[serde(default)]
@@ -217,6 +771,308 @@ pub struct TcpPort {
     pub port: u16,
 }
 
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UdpPort {
+    /// Container port to expose, using UDP.
+    pub port: u16,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceAccountOptions {
+    /// Run as an existing ServiceAccount, instead of one created for the application.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Annotations on the ServiceAccount, such as workload identity bindings.
+    /// Not valid together with `name`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+
+    /// Mount the default ServiceAccount token in the pods. Defaults to true.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub automount_token: Option<bool>,
+
+    /// Mount a projected ServiceAccount token with a custom audience.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub projected_token: Option<ProjectedToken>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectedToken {
+    /// The intended audience of the token.
+    pub audience: String,
+
+    /// The requested lifetime of the token. The kubelet rotates the token before it expires.
+    /// Defaults to 3600.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration_seconds: Option<i64>,
+
+    /// The directory to mount the token in, as the file `token`.
+    /// The default value is /var/run/secrets/yakup.ibidem.no/projected-token.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mount_path: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct KubernetesAccess {
+    /// The API groups of the resources. Use "" for the core API group, which is the default.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub api_groups: Vec<String>,
+
+    /// The resources, such as `configmaps` or `leases`.
+    pub resources: Vec<String>,
+
+    /// Limit access to the named objects.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub resource_names: Vec<String>,
+
+    /// The allowed verbs, such as `get`, `list` and `watch`.
+    pub verbs: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceOptions {
+    /// The type of Service. Defaults to ClusterIP.
+    #[serde(default)]
+    #[serde(rename = "type")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_type: Option<ServiceType>,
+
+    /// Send requests from the same client to the same pod.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_affinity: Option<SessionAffinity>,
+
+    /// Route external traffic to pods on the receiving node only, preserving the client address.
+    /// Only valid for LoadBalancer and NodePort services.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_traffic_policy: Option<ExternalTrafficPolicy>,
+
+    /// Annotations on the Service, such as settings for the load balancer.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub enum ImagePullPolicy {
+    Always,
+    #[default]
+    IfNotPresent,
+    Never,
+}
+
+impl Display for ImagePullPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub enum ServiceType {
+    #[default]
+    ClusterIP,
+    LoadBalancer,
+    NodePort,
+    /// A Service without a cluster IP, resolving to the addresses of the pods.
+    Headless,
+}
+
+impl Display for ServiceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub enum SessionAffinity {
+    #[default]
+    None,
+    ClientIP,
+}
+
+impl Display for SessionAffinity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, JsonSchema)]
+pub enum ExternalTrafficPolicy {
+    #[default]
+    Cluster,
+    Local,
+}
+
+impl Display for ExternalTrafficPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PodDisruptionBudget {
+    /// Set to false to not create a PodDisruptionBudget.
+    /// Set to true to create one even if the application has only one replica.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+
+    /// The number or percentage of pods that can be unavailable during a disruption.
+    /// Defaults to 1. It is an error to set both maxUnavailable and minAvailable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_unavailable: Option<IntOrString>,
+
+    /// The number or percentage of pods that must be available during a disruption.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_available: Option<IntOrString>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Rollout {
+    /// How to replace old pods with new ones. Defaults to RollingUpdate.
+    /// Use Recreate for applications that can not run two versions at the same time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<RolloutStrategy>,
+
+    /// The number or percentage of pods that can be created above the desired number during a RollingUpdate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_surge: Option<IntOrString>,
+
+    /// The number or percentage of pods that can be unavailable during a RollingUpdate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_unavailable: Option<IntOrString>,
+
+    /// The number of old revisions to keep for rollback.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision_history_limit: Option<i32>,
+
+    /// Seconds to wait for a rollout to make progress before it is considered failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress_deadline_seconds: Option<i32>,
+
+    /// Seconds a new pod must be ready before it is considered available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_ready_seconds: Option<i32>,
+
+    /// Seconds a pod is given to stop gracefully, including the preStop sleep.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub termination_grace_period_seconds: Option<i64>,
+
+    /// Seconds to wait before the container is sent SIGTERM, giving ingress controllers and
+    /// service proxies time to stop sending traffic to the pod. Set to 0 to disable.
+    /// Defaults to 5.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_stop_sleep_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema, PartialEq)]
+pub enum RolloutStrategy {
+    #[default]
+    RollingUpdate,
+    Recreate,
+}
+
+impl Display for RolloutStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Scheduling {
+    /// Only schedule pods on nodes with all of these labels.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub node_selector: BTreeMap<String, String>,
+
+    /// Allow pods to be scheduled on nodes with matching taints.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tolerations: Vec<Toleration>,
+
+    /// Spread pods evenly across the listed topology domains.
+    /// When set, this replaces the default pod anti-affinity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topology_spread: Option<TopologySpread>,
+
+    /// Affinity rules for the pods.
+    /// When set, this replaces the default pod anti-affinity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<Affinity>,
+
+    /// PriorityClass to use for the pods.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority_class_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySpread {
+    /// Node labels identifying the topology domains to spread across.
+    /// Defaults to zone and hostname.
+    #[serde(default = "default_topology_keys")]
+    pub topology_keys: Vec<String>,
+
+    /// The maximum difference in number of pods between two domains. Defaults to 1.
+    #[serde(default = "default_max_skew")]
+    pub max_skew: i32,
+
+    /// What to do with pods that can not be placed without exceeding maxSkew.
+    #[serde(default)]
+    pub when_unsatisfiable: WhenUnsatisfiable,
+}
+
+fn default_topology_keys() -> Vec<String> {
+    vec![
+        "topology.kubernetes.io/zone".to_string(),
+        "kubernetes.io/hostname".to_string(),
+    ]
+}
+
+fn default_max_skew() -> i32 {
+    1
+}
+
+impl Default for TopologySpread {
+    fn default() -> Self {
+        TopologySpread {
+            topology_keys: default_topology_keys(),
+            max_skew: default_max_skew(),
+            when_unsatisfiable: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+pub enum WhenUnsatisfiable {
+    #[default]
+    ScheduleAnyway,
+    DoNotSchedule,
+}
+
+impl Display for WhenUnsatisfiable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Probes {
@@ -281,3 +1137,46 @@ pub struct TcpAction {
     #[serde(flatten)]
     pub config: ProbeConfig,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_value(value: Option<&str>, value_from: Option<EnvValueFrom>) -> EnvValue {
+        EnvValue {
+            name: "TEST".to_string(),
+            value: value.map(|v| v.to_string()),
+            value_from,
+        }
+    }
+
+    #[test]
+    fn env_value_requires_exactly_one_of_value_and_value_from() {
+        let value_from = EnvValueFrom {
+            field_ref: Some(FieldRef::PodIP),
+            ..Default::default()
+        };
+        assert!(env_value(Some("test"), None).to_kube().is_ok());
+        assert!(env_value(None, Some(value_from.clone())).to_kube().is_ok());
+        assert!(env_value(None, None).to_kube().is_err());
+        assert!(env_value(Some("test"), Some(value_from)).to_kube().is_err());
+    }
+
+    #[test]
+    fn env_value_from_requires_exactly_one_source() {
+        let empty = EnvValueFrom::default();
+        assert!(env_value(None, Some(empty)).to_kube().is_err());
+
+        let both = EnvValueFrom {
+            field_ref: Some(FieldRef::Namespace),
+            secret_key_ref: Some(KeyRef {
+                name: "secret".to_string(),
+                key: "key".to_string(),
+                optional: None,
+                rollout_on_change: None,
+            }),
+            ..Default::default()
+        };
+        assert!(env_value(None, Some(both)).to_kube().is_err());
+    }
+}
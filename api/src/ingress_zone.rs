@@ -1,6 +1,10 @@
+use crate::application::AuthMode;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, LabelSelector};
 use kube::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
 
 pub mod v1 {
     use super::*;
@@ -10,22 +14,73 @@ pub mod v1 {
         group = "yakup.ibidem.no",
         version = "v1",
         kind = "IngressZone",
+        status = "IngressZoneStatus",
         shortname = "zone",
         doc = "Ingress Zone",
-        printcolumn = r#"{"name":"Host","type":"string","jsonPath":".spec.host"}"#
+        printcolumn = r#"{"name":"Host","type":"string","jsonPath":".spec.host"}"#,
+        printcolumn = r#"{"name":"Consumers","type":"integer","jsonPath":".status.consumerCount"}"#
     )]
     #[serde(rename_all = "camelCase")]
     pub struct IngressZoneSpec {
         /// The host to use for this zone.
-        /// Can contain a variable in the form `{appname}` which will be replaced with the application name.
+        /// Can contain the variables `{appname}`, `{namespace}`, `{team}` (from a label on the application
+        /// namespace) and `{label:<key>}` (from a label on the application).
+        /// The resulting host must be a valid DNS name.
         pub host: String,
 
+        /// Domains applications may request extra hostnames in.
+        /// A hostname is allowed if it is one of the domains, or a subdomain of one.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub allowed_domains: Vec<String>,
+
+        /// Restrict which namespaces may expose applications in this zone.
+        /// If not set, applications in all namespaces may use the zone.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub allowed_namespaces: Option<AllowedNamespaces>,
+
+        /// Annotations added to every Ingress or HTTPRoute in this zone.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        pub annotations: BTreeMap<String, String>,
+
+        /// Annotation keys applications may set on their Ingress or HTTPRoute in this zone.
+        /// A key ending in `*` allows all keys with that prefix.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        pub allowed_annotations: Vec<String>,
+
+        /// The kind of ingress controller serving this zone.
+        /// Required for the ingress `options` of applications, which are mapped to controller specific annotations.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ingress_controller: Option<IngressController>,
+
         /// IngressClass to use for this zone.
         /// If not set, the default class will be used.
         #[serde(default)]
         #[serde(skip_serializing_if = "Option::is_none")]
         pub ingress_class: Option<String>,
 
+        /// The namespace the ingress controller for this zone runs in.
+        /// Applications with an access policy allow traffic from this namespace to their http port.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub controller_namespace: Option<String>,
+
+        /// Authentication in front of the applications in this zone.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub auth: Option<ZoneAuth>,
+
+        /// Gateway to attach routes to for this zone.
+        /// When set, applications get a Gateway API HTTPRoute instead of an Ingress,
+        /// and `ingressClass` and `tls` are ignored.
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub gateway: Option<IngressZoneGateway>,
+
         /// TLS configuration for this zone.
         #[serde(default)]
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -40,3 +95,138 @@ pub struct IngressZoneTLS {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cluster_issuer: Option<String>,
 }
+
+/// An auth provider, either an oauth2-proxy deployment or an external auth service.
+/// Ingress zones need an `ingressController` for auth, gateway zones need a `gatewayFilter`.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneAuth {
+    /// The base URL of an oauth2-proxy deployment, such as `https://auth.example.com`.
+    /// The auth and sign-in URLs and the response headers are derived from it.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oauth2_proxy_url: Option<String>,
+
+    /// The URL of an external auth service.
+    /// Requests are allowed if it responds with a 2xx status.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// The URL unauthenticated users are redirected to for sign-in.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signin_url: Option<String>,
+
+    /// A URL of the external auth service that allows unauthenticated requests,
+    /// but still returns the identity of authenticated users. Required for `optional` auth.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional_url: Option<String>,
+
+    /// Headers from the auth response to pass on to the application.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub response_headers: Vec<String>,
+
+    /// The auth mode of applications that don't set one. Defaults to `none`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_mode: Option<AuthMode>,
+
+    /// Filter added to HTTPRoutes in gateway zones, for gateways with auth implemented as an extension.
+    /// The referenced object must exist in the namespace of each application.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gateway_filter: Option<GatewayFilterRef>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GatewayFilterRef {
+    pub group: String,
+    pub kind: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressZoneGateway {
+    /// The name of the Gateway.
+    pub name: String,
+
+    /// The namespace of the Gateway.
+    /// If not set, the Gateway must be in the same namespace as the application.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// The name of the Gateway listener to attach to.
+    /// If not set, routes are attached to all listeners that allow them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section_name: Option<String>,
+
+    /// The name of the plain HTTP listener of the Gateway.
+    /// Required for applications with `httpsRedirect`, which get a route redirecting this listener to HTTPS.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_section_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IngressZoneStatus {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<Condition>,
+
+    /// The number of applications exposed in this zone.
+    #[serde(default)]
+    pub consumer_count: u32,
+
+    /// The applications exposed in this zone.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub consumers: Vec<ZoneConsumer>,
+
+    /// The hostnames handed out to applications in this zone.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq, PartialOrd, Ord, JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ZoneConsumer {
+    pub namespace: String,
+    pub name: String,
+}
+
+/// A namespace is allowed if it is listed in `names`, or matches `selector`.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AllowedNamespaces {
+    /// Names of the allowed namespaces.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub names: Vec<String>,
+
+    /// Label selector for the allowed namespaces.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selector: Option<LabelSelector>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum IngressController {
+    /// The Kubernetes ingress-nginx controller.
+    Nginx,
+}
+
+impl Display for IngressController {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
@@ -0,0 +1,184 @@
+use std::env;
+use std::fs::File;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use api::application::Scheduling;
+
+/// Environment variable pointing to the controller configuration file.
+const CONFIG_FILE_ENV: &str = "YAKUP_CONFIG";
+
+pub const DEFAULT_SECRET_MOUNT_PATH: &str = "/var/run/secrets/yakup.ibidem.no";
+pub const DEFAULT_CONFIGMAP_MOUNT_PATH: &str = "/var/run/config/yakup.ibidem.no";
+
+/// Controller level configuration, shared by all applications.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    /// The OTLP endpoint injected as `OTEL_EXPORTER_OTLP_ENDPOINT` in every application.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otel_exporter_otlp_endpoint: Option<String>,
+
+    /// The configmaps and secrets every application gets configuration from implicitly.
+    #[serde(default)]
+    pub implicit_sources: ImplicitSources,
+
+    /// Default scheduling settings for all applications.
+    /// Applications can override each setting in `spec.scheduling`.
+    #[serde(default)]
+    pub scheduling: Scheduling,
+
+    /// The namespace label used for `{team}` in ingress zone host templates.
+    /// Defaults to `yakup.ibidem.no/team`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub team_label: Option<String>,
+
+    /// Names of secrets with registry credentials added to every application.
+    /// The secrets must exist in each application namespace.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub image_pull_secrets: Vec<String>,
+
+    /// Registries applications may run images from, such as `ghcr.io` or `ghcr.io/example-org`.
+    /// Images from other registries are not deployed. If empty, all registries are allowed.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_registries: Vec<String>,
+
+    /// Labels and annotations on applications that are copied to all generated objects.
+    #[serde(default)]
+    pub pass_through: PassThrough,
+
+    /// Deploy images by digest, resolving tags through the registry API.
+    /// Applications can override this in `spec.pinImageDigest`.
+    #[serde(default)]
+    pub pin_image_digests: bool,
+
+    /// The Kubernetes API access applications may request in `spec.kubernetesAccess`.
+    #[serde(default)]
+    pub kubernetes_access: KubernetesAccessPolicy,
+}
+
+/// Labels and annotations with keys starting with one of the prefixes are copied from the application
+/// to the objects generated for it, including the pods.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PassThrough {
+    /// Prefixes of labels to copy, such as `example.com/`.
+    pub labels: Vec<String>,
+
+    /// Prefixes of annotations to copy.
+    pub annotations: Vec<String>,
+}
+
+/// Applications may only be granted access to the Kubernetes API that is covered by the allowlist.
+/// Every combination of API group, resource and verb an application requests must be allowed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct KubernetesAccessPolicy {
+    /// The allowed grants.
+    /// Subresources, such as `pods/exec`, must be allowed explicitly.
+    pub allowed: Vec<AllowedAccess>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AllowedAccess {
+    /// The API group of the resources. Use "" for the core API group.
+    #[serde(default)]
+    pub api_group: String,
+
+    /// The allowed resources. `*` allows any resource in the API group, but not its subresources.
+    pub resources: Vec<String>,
+
+    /// The allowed verbs. `*` allows any verb.
+    pub verbs: Vec<String>,
+}
+
+impl AllowedAccess {
+    fn new(api_group: &str, resources: &[&str], verbs: &[&str]) -> Self {
+        AllowedAccess {
+            api_group: api_group.to_string(),
+            resources: resources.iter().map(|r| r.to_string()).collect(),
+            verbs: verbs.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+}
+
+impl Default for KubernetesAccessPolicy {
+    fn default() -> Self {
+        let read = ["get", "list", "watch"];
+        KubernetesAccessPolicy {
+            allowed: vec![
+                AllowedAccess::new("", &["configmaps", "endpoints", "pods", "services"], &read),
+                AllowedAccess::new("discovery.k8s.io", &["endpointslices"], &read),
+                AllowedAccess::new(
+                    "coordination.k8s.io",
+                    &["leases"],
+                    &[
+                        "get", "list", "watch", "create", "update", "patch", "delete",
+                    ],
+                ),
+                AllowedAccess::new("", &["events"], &["create", "patch"]),
+                AllowedAccess::new("events.k8s.io", &["events"], &["create", "patch"]),
+            ],
+        }
+    }
+}
+
+/// By convention every application reads configuration from a set of optional configmaps and secrets
+/// named after the application, exposed both as environment variables and as mounted files.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImplicitSources {
+    /// Set to false to disable implicit sources for all applications.
+    pub enabled: bool,
+
+    /// Name patterns for the implicit sources.
+    /// `{appname}` will be replaced with the application name.
+    pub names: Vec<String>,
+
+    /// Add implicit configmaps.
+    pub config_maps: bool,
+
+    /// Add implicit secrets.
+    pub secrets: bool,
+
+    /// Implicit configmaps are mounted at `<configMapMountRoot>/<name>`.
+    pub config_map_mount_root: String,
+
+    /// Implicit secrets are mounted at `<secretMountRoot>/<name>`.
+    pub secret_mount_root: String,
+}
+
+impl Default for ImplicitSources {
+    fn default() -> Self {
+        ImplicitSources {
+            enabled: true,
+            names: vec!["{appname}-db".to_string(), "{appname}".to_string()],
+            config_maps: true,
+            secrets: true,
+            config_map_mount_root: DEFAULT_CONFIGMAP_MOUNT_PATH.to_string(),
+            secret_mount_root: DEFAULT_SECRET_MOUNT_PATH.to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Load configuration from the YAML file named by `YAKUP_CONFIG`.
+    /// If the variable is not set, the default configuration is used.
+    pub fn load() -> Result<Config> {
+        match env::var(CONFIG_FILE_ENV) {
+            Ok(path) => {
+                let f = File::open(&path)
+                    .with_context(|| format!("opening configuration file {}", path))?;
+                serde_yaml::from_reader(f)
+                    .with_context(|| format!("parsing configuration file {}", path))
+            }
+            Err(_) => Ok(Config::default()),
+        }
+    }
+}
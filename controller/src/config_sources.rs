@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::core::v1::{ConfigMap, Secret};
+use kube::runtime::reflector::ObjectRef;
+use kube::{Api, Client, ResourceExt};
+use md5::{Digest, Md5};
+use tracing::instrument;
+
+use api::application::v1::Application;
+
+use crate::config::{Config, ImplicitSources};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SourceKind {
+    ConfigMap,
+    Secret,
+}
+
+impl Display for SourceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// A configmap or secret in the application namespace that the application reads configuration from.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ConfigSource {
+    pub kind: SourceKind,
+    pub name: String,
+}
+
+impl ConfigSource {
+    fn new(kind: SourceKind, name: &str) -> Self {
+        ConfigSource {
+            kind,
+            name: name.to_owned(),
+        }
+    }
+}
+
+/// The configmaps and secrets the application gets configuration from implicitly.
+pub fn implicit_sources(app: &Application, config: &ImplicitSources) -> Vec<ConfigSource> {
+    let mut sources = Vec::new();
+    if !config.enabled || !app.spec.implicit_sources.unwrap_or(true) {
+        return sources;
+    }
+    for pattern in config.names.iter() {
+        let name = pattern.replace("{appname}", app.name_any().as_str());
+        if config.config_maps {
+            sources.push(ConfigSource::new(SourceKind::ConfigMap, &name));
+        }
+        if config.secrets {
+            sources.push(ConfigSource::new(SourceKind::Secret, &name));
+        }
+    }
+    sources
+}
+
+/// The configmaps and secrets that should trigger a rollout of the application when they change.
+/// Sources that have opted out with `rolloutOnChange: false` or `implicitSourcesWithoutRollout`
+/// are not included.
+pub fn rollout_sources(app: &Application, config: &Config) -> Vec<ConfigSource> {
+    let mut sources = implicit_sources(app, &config.implicit_sources);
+    sources.retain(|source| {
+        !app.spec
+            .implicit_sources_without_rollout
+            .contains(&source.name)
+    });
+
+    for env in app.spec.env.iter() {
+        if let Some(value_from) = &env.value_from {
+            if let Some(key_ref) = &value_from.config_map_key_ref {
+                if key_ref.rollout_on_change.unwrap_or(true) {
+                    sources.push(ConfigSource::new(SourceKind::ConfigMap, &key_ref.name));
+                }
+            }
+            if let Some(key_ref) = &value_from.secret_key_ref {
+                if key_ref.rollout_on_change.unwrap_or(true) {
+                    sources.push(ConfigSource::new(SourceKind::Secret, &key_ref.name));
+                }
+            }
+        }
+    }
+
+    for ef in app.spec.env_from.iter() {
+        if !ef.rollout_on_change.unwrap_or(true) {
+            continue;
+        }
+        if let Some(name) = &ef.config_map {
+            sources.push(ConfigSource::new(SourceKind::ConfigMap, name));
+        }
+        if let Some(name) = &ef.secret {
+            sources.push(ConfigSource::new(SourceKind::Secret, name));
+        }
+    }
+
+    for ff in app.spec.files_from.iter() {
+        if let Some(ffcm) = &ff.config_map {
+            if ffcm.rollout_on_change.unwrap_or(true) {
+                sources.push(ConfigSource::new(SourceKind::ConfigMap, &ffcm.name));
+            }
+        }
+        if let Some(ffs) = &ff.secret {
+            if ffs.rollout_on_change.unwrap_or(true) {
+                sources.push(ConfigSource::new(SourceKind::Secret, &ffs.name));
+            }
+        }
+    }
+
+    sources.sort();
+    sources.dedup();
+    sources
+}
+
+/// Compute a hash of the contents of all rollout sources of the application.
+/// Sources that don't exist are part of the hash, so creating them later also triggers a rollout.
+/// Returns `None` if the application has no rollout sources.
+#[instrument(skip(client, app, config), fields(trace_id))]
+pub async fn compute_hash(
+    client: &Client,
+    app: &Application,
+    config: &Config,
+) -> Result<Option<String>> {
+    let sources = rollout_sources(app, config);
+    if sources.is_empty() {
+        return Ok(None);
+    }
+    let namespace = app.namespace().unwrap_or("default".to_string());
+    let config_maps = Api::<ConfigMap>::namespaced(client.clone(), &namespace);
+    let secrets = Api::<Secret>::namespaced(client.clone(), &namespace);
+
+    let mut hasher = Md5::new();
+    for source in sources.iter() {
+        hasher.update(format!("{}/{}\n", source.kind, source.name).as_bytes());
+        let contents = match source.kind {
+            SourceKind::ConfigMap => config_maps
+                .get_opt(&source.name)
+                .await
+                .map_err(|e| anyhow!(e).context(format!("getting configmap {}", source.name)))?
+                .map(config_map_contents),
+            SourceKind::Secret => secrets
+                .get_opt(&source.name)
+                .await
+                .map_err(|e| anyhow!(e).context(format!("getting secret {}", source.name)))?
+                .map(secret_contents),
+        };
+        match contents {
+            Some(contents) => {
+                for (key, value) in contents.iter() {
+                    hasher.update(key.as_bytes());
+                    hasher.update(b"=");
+                    hasher.update(value);
+                    hasher.update(b"\n");
+                }
+            }
+            None => hasher.update(b"<missing>\n"),
+        }
+    }
+    Ok(Some(
+        fast32::base32::CROCKFORD_LOWER.encode(&hasher.finalize()),
+    ))
+}
+
+fn config_map_contents(config_map: ConfigMap) -> BTreeMap<String, Vec<u8>> {
+    let mut contents = BTreeMap::new();
+    for (key, value) in config_map.data.unwrap_or_default() {
+        contents.insert(key, value.into_bytes());
+    }
+    for (key, value) in config_map.binary_data.unwrap_or_default() {
+        contents.insert(key, value.0);
+    }
+    contents
+}
+
+fn secret_contents(secret: Secret) -> BTreeMap<String, Vec<u8>> {
+    let mut contents = BTreeMap::new();
+    for (key, value) in secret.data.unwrap_or_default() {
+        contents.insert(key, value.0);
+    }
+    for (key, value) in secret.string_data.unwrap_or_default() {
+        contents.insert(key, value.into_bytes());
+    }
+    contents
+}
+
+/// Find the applications in the given namespace that should roll out when the named source changes.
+pub fn applications_using(
+    config: &Config,
+    apps: Vec<Arc<Application>>,
+    kind: SourceKind,
+    namespace: Option<String>,
+    name: &str,
+) -> Vec<ObjectRef<Application>> {
+    let source = ConfigSource::new(kind, name);
+    apps.iter()
+        .filter(|app| app.namespace() == namespace)
+        .filter(|app| rollout_sources(app, config).contains(&source))
+        .map(|app| ObjectRef::from_obj(app.as_ref()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use api::application::v1::ApplicationSpec;
+    use api::application::{EnvFrom, FilesFrom, FilesFromSecret};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn rollout_sources_skips_opted_out_sources() {
+        let app = Application::new(
+            "test-app",
+            ApplicationSpec {
+                env_from: vec![
+                    EnvFrom {
+                        config_map: Some("tracked".to_string()),
+                        ..Default::default()
+                    },
+                    EnvFrom {
+                        secret: Some("untracked".to_string()),
+                        rollout_on_change: Some(false),
+                        ..Default::default()
+                    },
+                ],
+                files_from: vec![FilesFrom {
+                    secret: Some(FilesFromSecret {
+                        name: "tracked".to_string(),
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        );
+
+        let names: Vec<String> = rollout_sources(&app, &Config::default())
+            .iter()
+            .map(|source| format!("{}/{}", source.kind, source.name))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "ConfigMap/test-app",
+                "ConfigMap/test-app-db",
+                "ConfigMap/tracked",
+                "Secret/test-app",
+                "Secret/test-app-db",
+                "Secret/tracked",
+            ]
+        );
+    }
+
+    #[test]
+    fn rollout_sources_skips_opted_out_implicit_sources() {
+        let app = Application::new(
+            "test-app",
+            ApplicationSpec {
+                implicit_sources_without_rollout: vec!["test-app-db".to_string()],
+                ..Default::default()
+            },
+        );
+
+        let names: Vec<String> = rollout_sources(&app, &Config::default())
+            .iter()
+            .map(|source| format!("{}/{}", source.kind, source.name))
+            .collect();
+        assert_eq!(names, vec!["ConfigMap/test-app", "Secret/test-app"]);
+        assert_eq!(
+            implicit_sources(&app, &Config::default().implicit_sources).len(),
+            4
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use anyhow::{anyhow, bail, Result};
+use kube::ResourceExt;
+
+use api::application::v1::Application;
+
+use crate::config::Config;
+use crate::resource_creator::ClusterState;
+
+/// Label on the application namespace used for `{team}`, unless configured otherwise.
+pub const DEFAULT_TEAM_LABEL: &str = "yakup.ibidem.no/team";
+
+const MAX_HOST_LENGTH: usize = 253;
+const MAX_DNS_LABEL_LENGTH: usize = 63;
+
+/// A variable in a host template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Variable {
+    /// `{appname}`: the name of the application.
+    AppName,
+    /// `{namespace}`: the namespace of the application.
+    Namespace,
+    /// `{team}`: the value of the team label on the application namespace.
+    Team,
+    /// `{label:<key>}`: the value of the named label on the application.
+    Label(String),
+}
+
+impl Display for Variable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Variable::AppName => write!(f, "{{appname}}"),
+            Variable::Namespace => write!(f, "{{namespace}}"),
+            Variable::Team => write!(f, "{{team}}"),
+            Variable::Label(key) => write!(f, "{{label:{}}}", key),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Variable(Variable),
+}
+
+/// A parsed `IngressZone` host template, such as `{appname}.{team}.example.com`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostTemplate {
+    segments: Vec<Segment>,
+}
+
+/// The values available to a host template when rendering the host of an application.
+#[derive(Debug, Clone, Default)]
+pub struct HostVariables {
+    pub app_name: String,
+    pub namespace: String,
+    pub team: Option<String>,
+    pub labels: BTreeMap<String, String>,
+}
+
+impl HostVariables {
+    pub fn new(app: &Application, config: &Config, cluster_state: &ClusterState) -> Self {
+        let team_label = config.team_label.as_deref().unwrap_or(DEFAULT_TEAM_LABEL);
+        HostVariables {
+            app_name: app.name_any(),
+            namespace: app.namespace().unwrap_or("default".to_string()),
+            team: cluster_state.namespace_labels.get(team_label).cloned(),
+            labels: app.labels().clone(),
+        }
+    }
+
+    fn get(&self, variable: &Variable) -> Result<&str> {
+        match variable {
+            Variable::AppName => Ok(&self.app_name),
+            Variable::Namespace => Ok(&self.namespace),
+            Variable::Team => self.team.as_deref().ok_or_else(|| {
+                anyhow!(
+                    "{} used in host, but namespace {} has no team label",
+                    variable,
+                    self.namespace
+                )
+            }),
+            Variable::Label(key) => self.labels.get(key).map(String::as_str).ok_or_else(|| {
+                anyhow!(
+                    "{} used in host, but application {} has no such label",
+                    variable,
+                    self.app_name
+                )
+            }),
+        }
+    }
+}
+
+impl HostTemplate {
+    /// Parse a host template, failing on unknown variables and unbalanced braces.
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                bail!("unexpected '}}' in host template {:?}", template);
+            }
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| anyhow!("unclosed '{{' in host template {:?}", template))?;
+            let name = &rest[start + 1..end];
+            let variable = match name {
+                "appname" => Variable::AppName,
+                "namespace" => Variable::Namespace,
+                "team" => Variable::Team,
+                _ => match name.strip_prefix("label:") {
+                    Some(key) if !key.is_empty() && !key.contains('{') => {
+                        Variable::Label(key.to_string())
+                    }
+                    _ => bail!(
+                        "unknown variable {{{}}} in host template {:?}",
+                        name,
+                        template
+                    ),
+                },
+            };
+            segments.push(Segment::Variable(variable));
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        Ok(HostTemplate { segments })
+    }
+
+    /// Render the host, and check that the result is a valid DNS name.
+    pub fn render(&self, variables: &HostVariables) -> Result<String> {
+        let mut host = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(literal) => host.push_str(literal),
+                Segment::Variable(variable) => host.push_str(variables.get(variable)?),
+            }
+        }
+        validate_host(&host)?;
+        Ok(host)
+    }
+}
+
+/// Check that the host is a valid lowercase DNS name, optionally starting with a wildcard label.
+pub fn validate_host(host: &str) -> Result<()> {
+    if host.is_empty() {
+        bail!("host is empty");
+    }
+    if host.len() > MAX_HOST_LENGTH {
+        bail!(
+            "host {:?} is longer than {} characters",
+            host,
+            MAX_HOST_LENGTH
+        );
+    }
+    for (i, label) in host.split('.').enumerate() {
+        if i == 0 && label == "*" {
+            continue;
+        }
+        if label.is_empty() {
+            bail!("host {:?} has an empty DNS label", host);
+        }
+        if label.len() > MAX_DNS_LABEL_LENGTH {
+            bail!(
+                "DNS label {:?} in host {:?} is longer than {} characters",
+                label,
+                host,
+                MAX_DNS_LABEL_LENGTH
+            );
+        }
+        if !label
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        {
+            bail!(
+                "DNS label {:?} in host {:?} may only contain lowercase letters, digits and '-'",
+                label,
+                host
+            );
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            bail!(
+                "DNS label {:?} in host {:?} must start and end with a letter or digit",
+                label,
+                host
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn variables() -> HostVariables {
+        HostVariables {
+            app_name: "test-app".to_string(),
+            namespace: "shop".to_string(),
+            team: Some("payments".to_string()),
+            labels: BTreeMap::from([("tier".to_string(), "frontend".to_string())]),
+        }
+    }
+
+    #[test]
+    fn render_all_variables() {
+        let template =
+            HostTemplate::parse("{appname}-{label:tier}.{namespace}.{team}.example.com").unwrap();
+        assert_eq!(
+            template.render(&variables()).unwrap(),
+            "test-app-frontend.shop.payments.example.com"
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_templates() {
+        for template in [
+            "{app}.example.com",
+            "{appname.example.com",
+            "appname}.example.com",
+            "{label:}.example.com",
+        ] {
+            assert!(HostTemplate::parse(template).is_err(), "{}", template);
+        }
+    }
+
+    #[test]
+    fn render_rejects_invalid_hosts() {
+        let mut vars = variables();
+        vars.app_name = "a".repeat(64);
+        for (template, vars) in [
+            ("{appname}.example.com", vars),
+            ("{appname}_x.example.com", variables()),
+            ("-{appname}.example.com", variables()),
+            ("{appname}..example.com", variables()),
+            ("{label:missing}.example.com", variables()),
+            (
+                "{team}.example.com",
+                HostVariables {
+                    team: None,
+                    ..variables()
+                },
+            ),
+        ] {
+            let template = HostTemplate::parse(template).unwrap();
+            assert!(template.render(&vars).is_err(), "{:?}", template);
+        }
+    }
+}
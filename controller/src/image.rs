@@ -0,0 +1,748 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use k8s_openapi::api::core::v1::Secret;
+use kube::{Api, Client, ResourceExt};
+use reqwest::header::{ACCEPT, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::{Response, StatusCode, Url};
+use serde::Deserialize;
+use tracing::{info, instrument};
+
+use api::application::v1::Application;
+
+use crate::config::Config;
+
+/// Registry used for images without an explicit registry.
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+
+/// The host serving the registry API for Docker Hub.
+const DOCKER_HUB_API_HOST: &str = "registry-1.docker.io";
+
+const DIGEST_HEADER: &str = "Docker-Content-Digest";
+
+/// The key holding the Docker config in `kubernetes.io/dockerconfigjson` secrets.
+const DOCKER_CONFIG_KEY: &str = ".dockerconfigjson";
+
+/// Manifest types accepted when resolving a tag, preferring multi-platform indexes.
+const MANIFEST_MEDIA_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+/// A parsed container image reference, such as `ghcr.io/example-org/app:1.0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageReference {
+    /// Parse an image reference, normalizing short Docker Hub names like `busybox`.
+    pub fn parse(image: &str) -> Result<Self> {
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (image, None),
+        };
+        // A ':' after the last '/' separates the tag, anything before it can be a registry port
+        let (name, tag) = match name.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag.to_string())),
+            _ => (name, None),
+        };
+        if name.is_empty() || name.split('/').any(str::is_empty) {
+            bail!("invalid image reference {:?}", image);
+        }
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest))
+                if first.contains('.') || first.contains(':') || first == "localhost" =>
+            {
+                (first.to_string(), rest.to_string())
+            }
+            Some(_) => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+            None => (DEFAULT_REGISTRY.to_string(), format!("library/{}", name)),
+        };
+        Ok(ImageReference {
+            registry,
+            repository,
+            tag,
+            digest,
+        })
+    }
+}
+
+impl ImageReference {
+    fn manifest_url(&self) -> String {
+        let host = if self.registry == DEFAULT_REGISTRY {
+            DOCKER_HUB_API_HOST
+        } else {
+            &self.registry
+        };
+        // Like container runtimes, only trust plain HTTP for registries on the local host
+        let scheme = match host.split(':').next() {
+            Some("localhost") | Some("127.0.0.1") => "http",
+            _ => "https",
+        };
+        format!(
+            "{}://{}/v2/{}/manifests/{}",
+            scheme,
+            host,
+            self.repository,
+            self.tag.as_deref().unwrap_or("latest")
+        )
+    }
+}
+
+impl Display for ImageReference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that the image comes from one of the registries allowed in the controller configuration.
+pub fn check_registry(image: &str, config: &Config) -> Result<()> {
+    let reference = ImageReference::parse(image)?;
+    if config.allowed_registries.is_empty() {
+        return Ok(());
+    }
+    let name = format!("{}/{}", reference.registry, reference.repository);
+    let allowed = config.allowed_registries.iter().any(|allowed| {
+        let allowed = allowed.trim_end_matches('/');
+        name.strip_prefix(allowed)
+            .is_some_and(|rest| rest.starts_with('/'))
+    });
+    if !allowed {
+        bail!(
+            "image {} is not from an allowed registry: {}",
+            image,
+            config.allowed_registries.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// The image to deploy when tags are pinned to digests, or None to deploy `spec.image` as is.
+/// The image recorded in the status is reused until the generation of the application changes.
+/// Private registries are accessed with the credentials in the image pull secrets.
+#[instrument(skip(http, client, app, config), fields(trace_id))]
+pub async fn resolved_image(
+    http: &reqwest::Client,
+    client: &Client,
+    app: &Application,
+    config: &Config,
+) -> Result<Option<String>> {
+    if !app
+        .spec
+        .pin_image_digest
+        .unwrap_or(config.pin_image_digests)
+    {
+        return Ok(None);
+    }
+    let reference = ImageReference::parse(&app.spec.image)?;
+    if reference.digest.is_some() {
+        return Ok(None);
+    }
+    if let Some(status) = &app.status {
+        if status.resolved_image.is_some()
+            && status.resolved_image_generation == app.metadata.generation
+        {
+            return Ok(status.resolved_image.clone());
+        }
+    }
+    let credentials = pull_credentials(client, app, config, &reference.registry).await?;
+    let digest = resolve_digest(http, &reference, credentials.as_ref()).await?;
+    info!("Resolved image {} to digest {}", app.spec.image, digest);
+    Ok(Some(format!("{}@{}", app.spec.image, digest)))
+}
+
+/// Credentials for a registry, from a Docker config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: BTreeMap<String, DockerConfigAuth>,
+}
+
+#[derive(Deserialize)]
+struct DockerConfigAuth {
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    auth: Option<String>,
+}
+
+/// Find credentials for the registry in the image pull secrets of the application, starting with
+/// the defaults from the controller configuration, like the kubelet does.
+pub async fn pull_credentials(
+    client: &Client,
+    app: &Application,
+    config: &Config,
+    registry: &str,
+) -> Result<Option<RegistryCredentials>> {
+    let namespace = app.namespace().unwrap_or("default".to_string());
+    let secrets = Api::<Secret>::namespaced(client.clone(), &namespace);
+    for name in config
+        .image_pull_secrets
+        .iter()
+        .chain(app.spec.image_pull_secrets.iter())
+    {
+        let secret = secrets
+            .get_opt(name)
+            .await
+            .map_err(|e| anyhow!(e).context(format!("getting image pull secret {}", name)))?;
+        if let Some(credentials) = secret
+            .map(|secret| docker_config_credentials(&secret, registry))
+            .transpose()?
+            .flatten()
+        {
+            return Ok(Some(credentials));
+        }
+    }
+    Ok(None)
+}
+
+/// The credentials for the registry in a `kubernetes.io/dockerconfigjson` secret, if it has any.
+fn docker_config_credentials(
+    secret: &Secret,
+    registry: &str,
+) -> Result<Option<RegistryCredentials>> {
+    let Some(data) = secret
+        .data
+        .as_ref()
+        .and_then(|data| data.get(DOCKER_CONFIG_KEY))
+    else {
+        return Ok(None);
+    };
+    let docker_config: DockerConfig = serde_json::from_slice(&data.0)
+        .with_context(|| format!("parsing image pull secret {}", secret.name_any()))?;
+    let Some(auth) = docker_config
+        .auths
+        .iter()
+        .find(|(server, _)| registry_host(server) == registry_host(registry))
+        .map(|(_, auth)| auth)
+    else {
+        return Ok(None);
+    };
+    if let (Some(username), Some(password)) = (&auth.username, &auth.password) {
+        return Ok(Some(RegistryCredentials {
+            username: username.clone(),
+            password: password.clone(),
+        }));
+    }
+    let Some(encoded) = &auth.auth else {
+        return Ok(None);
+    };
+    let decoded = BASE64_STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .ok_or_else(|| {
+            anyhow!(
+                "invalid auth for {} in image pull secret {}",
+                registry,
+                secret.name_any()
+            )
+        })?;
+    let (username, password) = decoded.split_once(':').ok_or_else(|| {
+        anyhow!(
+            "invalid auth for {} in image pull secret {}",
+            registry,
+            secret.name_any()
+        )
+    })?;
+    Ok(Some(RegistryCredentials {
+        username: username.to_string(),
+        password: password.to_string(),
+    }))
+}
+
+/// The host of a registry in a Docker config, where Docker Hub is often `https://index.docker.io/v1/`.
+fn registry_host(server: &str) -> &str {
+    let host = server
+        .split_once("://")
+        .map_or(server, |(_, rest)| rest)
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match host {
+        "index.docker.io" | DOCKER_HUB_API_HOST => DEFAULT_REGISTRY,
+        host => host,
+    }
+}
+
+/// Resolve the tag of the image to a digest, using the OCI distribution API.
+/// Registries requiring authentication are accessed with Basic auth or a pull token, using the
+/// credentials if there are any, and anonymously otherwise.
+pub async fn resolve_digest(
+    http: &reqwest::Client,
+    reference: &ImageReference,
+    credentials: Option<&RegistryCredentials>,
+) -> Result<String> {
+    let url = reference.manifest_url();
+    let mut response = manifest_head(http, &url, None).await?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        let authorization = authorization(http, &url, &response, credentials).await?;
+        response = manifest_head(http, &url, Some(&authorization)).await?;
+    }
+    if !response.status().is_success() {
+        bail!(
+            "resolving image {}: registry responded with {}",
+            reference,
+            response.status()
+        );
+    }
+    response
+        .headers()
+        .get(DIGEST_HEADER)
+        .and_then(|digest| digest.to_str().ok())
+        .map(String::from)
+        .ok_or_else(|| anyhow!("resolving image {}: registry returned no digest", reference))
+}
+
+async fn manifest_head(
+    http: &reqwest::Client,
+    url: &str,
+    authorization: Option<&str>,
+) -> Result<Response> {
+    let mut request = http.head(url).header(ACCEPT, MANIFEST_MEDIA_TYPES);
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    request
+        .send()
+        .await
+        .map_err(|e| anyhow!(e).context(format!("requesting manifest {}", url)))
+}
+
+/// The `Authorization` header answering the challenge in the `WWW-Authenticate` header of the response.
+async fn authorization(
+    http: &reqwest::Client,
+    url: &str,
+    response: &Response,
+    credentials: Option<&RegistryCredentials>,
+) -> Result<String> {
+    let challenge = response
+        .headers()
+        .get(WWW_AUTHENTICATE)
+        .and_then(|challenge| challenge.to_str().ok())
+        .ok_or_else(|| anyhow!("registry requires authentication, but sent no challenge"))?;
+    if let Some(params) = challenge.strip_prefix("Bearer ") {
+        let token = registry_token(http, url, &challenge_params(params), credentials).await?;
+        return Ok(format!("Bearer {}", token));
+    }
+    match credentials {
+        Some(credentials) if challenge.starts_with("Basic") => Ok(format!(
+            "Basic {}",
+            BASE64_STANDARD.encode(format!("{}:{}", credentials.username, credentials.password))
+        )),
+        Some(_) => bail!("unsupported registry authentication {:?}", challenge),
+        None => {
+            bail!("registry requires authentication, but there are no image pull secrets for it")
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    access_token: Option<String>,
+}
+
+/// Get a pull token from the realm of a Bearer challenge to a request for the URL.
+/// The realm must use HTTPS or be on the registry itself, as it gets the credentials.
+async fn registry_token(
+    http: &reqwest::Client,
+    url: &str,
+    params: &[(String, String)],
+    credentials: Option<&RegistryCredentials>,
+) -> Result<String> {
+    let realm = params
+        .iter()
+        .find(|(key, _)| key == "realm")
+        .map(|(_, value)| value.clone())
+        .ok_or_else(|| anyhow!("registry authentication challenge has no realm"))?;
+    let realm = Url::parse(&realm)
+        .with_context(|| format!("invalid registry authentication realm {:?}", realm))?;
+    let registry = Url::parse(url).with_context(|| format!("invalid registry URL {:?}", url))?;
+    if realm.scheme() != "https"
+        && (realm.host_str() != registry.host_str()
+            || realm.port_or_known_default() != registry.port_or_known_default())
+    {
+        bail!(
+            "registry authentication realm {} uses neither HTTPS nor the registry host",
+            realm
+        );
+    }
+    let query: Vec<&(String, String)> = params
+        .iter()
+        .filter(|(key, _)| key == "service" || key == "scope")
+        .collect();
+    let mut request = http.get(realm).query(&query);
+    if let Some(credentials) = credentials {
+        request = request.basic_auth(&credentials.username, Some(&credentials.password));
+    }
+    let token: TokenResponse = request
+        .send()
+        .await
+        .and_then(Response::error_for_status)
+        .map_err(|e| anyhow!(e).context("requesting registry token"))?
+        .json()
+        .await
+        .map_err(|e| anyhow!(e).context("parsing registry token"))?;
+    token
+        .token
+        .or(token.access_token)
+        .ok_or_else(|| anyhow!("registry token response has no token"))
+}
+
+/// Parse the comma separated `key="value"` parameters of an authentication challenge.
+fn challenge_params(params: &str) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+    let mut rest = params.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, remainder) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, remainder)) => (value, remainder),
+                None => (quoted, ""),
+            },
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        result.push((key, value.to_string()));
+        rest = remainder;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use api::application::v1::ApplicationSpec;
+    use api::application::ApplicationStatus;
+    use kube::api::ObjectMeta;
+    use pretty_assertions::assert_eq;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    const DIGEST: &str = "sha256:4c1f2f5e8e5f7c0a6d7e9b3a2c1d0e9f8a7b6c5d4e3f2a1b0c9d8e7f6a5b4c3d";
+
+    /// A Kubernetes client for the API server mocked by the server.
+    fn kube_client(server: &MockServer) -> Client {
+        Client::try_from(kube::Config::new(server.uri().parse().unwrap())).unwrap()
+    }
+
+    fn application(image: String, status: Option<ApplicationStatus>) -> Application {
+        Application {
+            metadata: ObjectMeta {
+                name: Some("test-app".to_string()),
+                namespace: Some("default".to_string()),
+                generation: Some(2),
+                ..Default::default()
+            },
+            spec: ApplicationSpec {
+                image,
+                pin_image_digest: Some(true),
+                ..Default::default()
+            },
+            status,
+        }
+    }
+
+    #[test]
+    fn parse_normalizes_references() {
+        for (image, expected) in [
+            ("busybox", "docker.io/library/busybox"),
+            ("busybox:latest", "docker.io/library/busybox:latest"),
+            ("example/app:1.0", "docker.io/example/app:1.0"),
+            ("localhost:5000/app:1.0", "localhost:5000/app:1.0"),
+            (
+                "ghcr.io/example-org/app@sha256:abc",
+                "ghcr.io/example-org/app@sha256:abc",
+            ),
+        ] {
+            assert_eq!(ImageReference::parse(image).unwrap().to_string(), expected);
+        }
+        assert!(ImageReference::parse("ghcr.io//app").is_err());
+    }
+
+    #[test]
+    fn check_registry_matches_registry_and_path_prefix() {
+        let config = Config {
+            allowed_registries: vec!["ghcr.io/example-org".to_string(), "docker.io".to_string()],
+            ..Default::default()
+        };
+        for image in ["ghcr.io/example-org/app:1.0", "busybox:latest"] {
+            assert!(check_registry(image, &config).is_ok(), "{}", image);
+        }
+        for image in [
+            "ghcr.io/example-organization/app",
+            "quay.io/example-org/app",
+        ] {
+            assert!(check_registry(image, &config).is_err(), "{}", image);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_digest_from_registry() {
+        let registry = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/v2/example/app/manifests/1.0"))
+            .respond_with(ResponseTemplate::new(200).insert_header(DIGEST_HEADER, DIGEST))
+            .expect(1)
+            .mount(&registry)
+            .await;
+
+        let image = format!("{}/example/app:1.0", registry.address());
+        let app = application(image.clone(), None);
+        let client = kube_client(&registry);
+        let resolved = resolved_image(&reqwest::Client::new(), &client, &app, &Config::default())
+            .await
+            .unwrap();
+        assert_eq!(resolved, Some(format!("{}@{}", image, DIGEST)));
+    }
+
+    #[tokio::test]
+    async fn resolve_digest_with_anonymous_token() {
+        let registry = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(path("/v2/example/app/manifests/latest"))
+            .and(header("Authorization", "Bearer anonymous"))
+            .respond_with(ResponseTemplate::new(200).insert_header(DIGEST_HEADER, DIGEST))
+            .with_priority(1)
+            .mount(&registry)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/v2/example/app/manifests/latest"))
+            .respond_with(ResponseTemplate::new(401).insert_header(
+                "WWW-Authenticate",
+                format!(
+                    r#"Bearer realm="{}/token",service="registry",scope="repository:example/app:pull""#,
+                    registry.uri()
+                ),
+            ))
+            .mount(&registry)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/token"))
+            .and(query_param("service", "registry"))
+            .and(query_param("scope", "repository:example/app:pull"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"token": "anonymous"})),
+            )
+            .mount(&registry)
+            .await;
+
+        let reference =
+            ImageReference::parse(&format!("{}/example/app", registry.address())).unwrap();
+        let digest = resolve_digest(&reqwest::Client::new(), &reference, None)
+            .await
+            .unwrap();
+        assert_eq!(digest, DIGEST);
+    }
+
+    #[tokio::test]
+    async fn resolved_image_reused_until_generation_changes() {
+        let registry = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .respond_with(ResponseTemplate::new(200).insert_header(DIGEST_HEADER, DIGEST))
+            .expect(1)
+            .mount(&registry)
+            .await;
+
+        let image = format!("{}/example/app:1.0", registry.address());
+        let status = ApplicationStatus {
+            resolved_image: Some(format!("{}@sha256:old", image)),
+            resolved_image_generation: Some(2),
+            ..Default::default()
+        };
+        let http = reqwest::Client::new();
+        let client = kube_client(&registry);
+        let mut app = application(image.clone(), Some(status));
+        let resolved = resolved_image(&http, &client, &app, &Config::default())
+            .await
+            .unwrap();
+        assert_eq!(resolved, Some(format!("{}@sha256:old", image)));
+
+        app.metadata.generation = Some(3);
+        let resolved = resolved_image(&http, &client, &app, &Config::default())
+            .await
+            .unwrap();
+        assert_eq!(resolved, Some(format!("{}@{}", image, DIGEST)));
+    }
+
+    #[test]
+    fn docker_config_credentials_for_registry() {
+        let docker_config = serde_json::json!({"auths": {
+            "https://index.docker.io/v1/": {"auth": BASE64_STANDARD.encode("hub-user:hub-pass")},
+            "ghcr.io": {"username": "ghcr-user", "password": "ghcr-pass"},
+        }});
+        let secret = Secret {
+            data: Some(BTreeMap::from([(
+                DOCKER_CONFIG_KEY.to_string(),
+                k8s_openapi::ByteString(serde_json::to_vec(&docker_config).unwrap()),
+            )])),
+            ..Default::default()
+        };
+        for (registry, username, password) in [
+            (DEFAULT_REGISTRY, "hub-user", "hub-pass"),
+            ("ghcr.io", "ghcr-user", "ghcr-pass"),
+        ] {
+            assert_eq!(
+                docker_config_credentials(&secret, registry).unwrap(),
+                Some(RegistryCredentials {
+                    username: username.to_string(),
+                    password: password.to_string(),
+                })
+            );
+        }
+        assert_eq!(docker_config_credentials(&secret, "quay.io").unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn resolve_digest_with_pull_secret() {
+        let registry = MockServer::start().await;
+        let docker_config = serde_json::json!({"auths": {
+            registry.address().to_string(): {"username": "puller", "password": "secret"},
+        }});
+        Mock::given(method("GET"))
+            .and(path("/api/v1/namespaces/default/secrets/registry"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "apiVersion": "v1",
+                "kind": "Secret",
+                "metadata": {"name": "registry", "namespace": "default"},
+                "type": "kubernetes.io/dockerconfigjson",
+                "data": {
+                    DOCKER_CONFIG_KEY: BASE64_STANDARD.encode(docker_config.to_string()),
+                },
+            })))
+            .mount(&registry)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/v2/example/app/manifests/1.0"))
+            .and(header("Authorization", "Bearer private"))
+            .respond_with(ResponseTemplate::new(200).insert_header(DIGEST_HEADER, DIGEST))
+            .with_priority(1)
+            .mount(&registry)
+            .await;
+        Mock::given(method("HEAD"))
+            .and(path("/v2/example/app/manifests/1.0"))
+            .respond_with(ResponseTemplate::new(401).insert_header(
+                "WWW-Authenticate",
+                format!(
+                    r#"Bearer realm="{}/token",service="registry""#,
+                    registry.uri()
+                ),
+            ))
+            .mount(&registry)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/token"))
+            .and(header(
+                "Authorization",
+                format!("Basic {}", BASE64_STANDARD.encode("puller:secret")).as_str(),
+            ))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"token": "private"})),
+            )
+            .expect(1)
+            .mount(&registry)
+            .await;
+
+        let image = format!("{}/example/app:1.0", registry.address());
+        let mut app = application(image.clone(), None);
+        app.spec.image_pull_secrets = vec!["registry".to_string()];
+        let resolved = resolved_image(
+            &reqwest::Client::new(),
+            &kube_client(&registry),
+            &app,
+            &Config::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resolved, Some(format!("{}@{}", image, DIGEST)));
+    }
+
+    #[tokio::test]
+    async fn resolve_digest_with_basic_auth() {
+        let registry = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .and(header(
+                "Authorization",
+                format!("Basic {}", BASE64_STANDARD.encode("puller:secret")).as_str(),
+            ))
+            .respond_with(ResponseTemplate::new(200).insert_header(DIGEST_HEADER, DIGEST))
+            .with_priority(1)
+            .mount(&registry)
+            .await;
+        Mock::given(method("HEAD"))
+            .respond_with(
+                ResponseTemplate::new(401)
+                    .insert_header("WWW-Authenticate", r#"Basic realm="registry""#),
+            )
+            .mount(&registry)
+            .await;
+
+        let reference =
+            ImageReference::parse(&format!("{}/example/app", registry.address())).unwrap();
+        let credentials = RegistryCredentials {
+            username: "puller".to_string(),
+            password: "secret".to_string(),
+        };
+        let http = reqwest::Client::new();
+        let digest = resolve_digest(&http, &reference, Some(&credentials))
+            .await
+            .unwrap();
+        assert_eq!(digest, DIGEST);
+        assert!(resolve_digest(&http, &reference, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn registry_token_rejects_plain_http_realm_on_other_host() {
+        let registry = MockServer::start().await;
+        let other = MockServer::start().await;
+        Mock::given(method("HEAD"))
+            .respond_with(ResponseTemplate::new(401).insert_header(
+                "WWW-Authenticate",
+                format!(r#"Bearer realm="{}/token",service="registry""#, other.uri()),
+            ))
+            .mount(&registry)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({"token": "x"})),
+            )
+            .expect(0)
+            .mount(&other)
+            .await;
+
+        let reference =
+            ImageReference::parse(&format!("{}/example/app", registry.address())).unwrap();
+        let credentials = RegistryCredentials {
+            username: "puller".to_string(),
+            password: "secret".to_string(),
+        };
+        let err = resolve_digest(&reqwest::Client::new(), &reference, Some(&credentials))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("neither HTTPS"), "{err}");
+    }
+}
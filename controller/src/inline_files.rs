@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::apps::v1::{Deployment, StatefulSet};
+use k8s_openapi::api::core::v1::{ConfigMap, PodTemplateSpec};
+use kube::api::ListParams;
+use kube::{Api, Client, ResourceExt};
+use md5::{Digest, Md5};
+use tracing::instrument;
+
+use api::application::v1::Application;
+
+use crate::resource_creator::is_stateful;
+
+/// Label set on the configmaps generated from `files`, with the application name as value.
+pub const INLINE_FILES_LABEL: &str = "yakup.ibidem.no/inline-files";
+
+/// Configmaps generated for earlier versions of `files`.
+#[derive(Debug, Default)]
+pub struct StaleConfigMaps {
+    /// Configmaps that are no longer used by any pods, and can be deleted.
+    pub unused: Vec<String>,
+    /// True if there are stale configmaps that are still in use by a rollout in progress.
+    pub in_use: bool,
+}
+
+/// The name of the configmap generated from `files`, including a hash of the contents.
+/// Returns `None` if the application has no `files`.
+pub fn config_map_name(app: &Application) -> Option<String> {
+    if app.spec.files.is_empty() {
+        return None;
+    }
+    let mut hasher = Md5::new();
+    for (name, contents) in app.spec.files.iter() {
+        hasher.update(name.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(contents.as_bytes());
+        hasher.update(b"\0");
+    }
+    let hash = fast32::base32::CROCKFORD_LOWER.encode(&hasher.finalize());
+    Some(format!("{}-files-{}", app.name_any(), &hash[..10]))
+}
+
+/// Find configmaps generated for earlier versions of `files`.
+/// A stale configmap is only unused once the workload has been updated to not reference it,
+/// and the rollout of the update has completed.
+#[instrument(skip(client, app), fields(trace_id))]
+pub async fn find_stale_config_maps(client: &Client, app: &Application) -> Result<StaleConfigMaps> {
+    let namespace = app.namespace().unwrap_or("default".to_string());
+    let config_maps = Api::<ConfigMap>::namespaced(client.clone(), &namespace);
+    let current = config_map_name(app);
+    let selector = format!("{}={}", INLINE_FILES_LABEL, app.name_any());
+    let stale: Vec<String> = config_maps
+        .list_metadata(&ListParams::default().labels(&selector))
+        .await
+        .map_err(|e| anyhow!(e).context("listing inline files configmaps"))?
+        .items
+        .iter()
+        .map(|cm| cm.name_any())
+        .filter(|name| Some(name) != current.as_ref())
+        .collect();
+    if stale.is_empty() {
+        return Ok(StaleConfigMaps::default());
+    }
+
+    let rollout = if is_stateful(app) {
+        Api::<StatefulSet>::namespaced(client.clone(), &namespace)
+            .get_opt(&app.name_any())
+            .await
+            .map_err(|e| anyhow!(e).context("getting statefulset"))?
+            .map(|sts| {
+                let spec = sts.spec.unwrap_or_default();
+                let status = sts.status.unwrap_or_default();
+                let done = status.observed_generation >= sts.metadata.generation
+                    && status.current_revision == status.update_revision
+                    && status.updated_replicas == spec.replicas;
+                (spec.template, done)
+            })
+    } else {
+        Api::<Deployment>::namespaced(client.clone(), &namespace)
+            .get_opt(&app.name_any())
+            .await
+            .map_err(|e| anyhow!(e).context("getting deployment"))?
+            .map(|deployment| {
+                let spec = deployment.spec.unwrap_or_default();
+                let status = deployment.status.unwrap_or_default();
+                let done = status.observed_generation >= deployment.metadata.generation
+                    && status.updated_replicas == spec.replicas
+                    && status.replicas == spec.replicas
+                    && status.available_replicas == spec.replicas;
+                (spec.template, done)
+            })
+    };
+
+    match rollout {
+        Some((template, done)) => {
+            let referenced = referenced_config_maps(&template);
+            let unused_by_template = stale.iter().all(|name| !referenced.contains(name));
+            if unused_by_template && done {
+                Ok(StaleConfigMaps {
+                    unused: stale,
+                    in_use: false,
+                })
+            } else {
+                Ok(StaleConfigMaps {
+                    unused: vec![],
+                    in_use: true,
+                })
+            }
+        }
+        None => Ok(StaleConfigMaps {
+            unused: stale,
+            in_use: false,
+        }),
+    }
+}
+
+fn referenced_config_maps(template: &PodTemplateSpec) -> Vec<String> {
+    template
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.volumes.as_ref())
+        .map(|volumes| {
+            volumes
+                .iter()
+                .filter_map(|v| v.config_map.as_ref().map(|cm| cm.name.clone()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
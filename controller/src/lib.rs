@@ -1,13 +1,16 @@
 use futures::join;
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use futures::StreamExt;
+use k8s_openapi::api::core::v1::{ConfigMap, Namespace, Secret, Service};
 use kube::runtime::controller::Action;
 use kube::runtime::controller::Controller;
-use kube::{Api, Client};
+use kube::runtime::reflector::{self, ObjectRef, Store};
+use kube::runtime::{watcher, WatchStreamExt};
+use kube::{Api, Client, ResourceExt};
 use opentelemetry::trace::{TraceId, TracerProvider};
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::SpanExporter;
@@ -24,8 +27,21 @@ use tracing_subscriber::{EnvFilter, Registry};
 use api::application::v1::Application;
 use api::ingress_zone::v1::IngressZone;
 
+use crate::config::Config;
+use crate::config_sources::SourceKind;
+use crate::resource_creator::ClusterState;
+
+pub mod config;
+pub mod config_sources;
+pub mod host_template;
+pub mod image;
+pub mod inline_files;
 pub mod models;
 pub mod resource_creator;
+pub mod status;
+pub mod workloads;
+pub mod zone_access;
+pub mod zone_status;
 
 #[derive(thiserror::Error, Debug)]
 enum ReconcilerError {
@@ -35,13 +51,35 @@ enum ReconcilerError {
     ResourceProcessing,
     #[error("applying operations")]
     ApplyOperations,
+    #[error("computing configuration hash")]
+    ConfigHash,
+    #[error("finding stale inline files configmaps")]
+    StaleConfigMaps,
+    #[error("getting application namespace")]
+    NamespaceLookup,
+    #[error("updating application status")]
+    UpdateStatus,
+    #[error("updating ingress zone status")]
+    ZoneStatus,
+    #[error("finding application workloads")]
+    WorkloadLookup,
+    #[error("getting application service")]
+    ServiceLookup,
+    #[error("discovering the Gateway API")]
+    GatewayApiLookup,
+    #[error("finding host conflicts")]
+    HostConflicts,
 }
 
 type ReconcileResult<T, E = ReconcilerError> = std::result::Result<T, E>;
 
 pub struct Context {
     pub client: Client,
+    pub config: Config,
+    pub http: reqwest::Client,
     pub ingress_zones: RwLock<HashMap<String, Arc<IngressZone>>>,
+    pub apps: Store<Application>,
+    pub namespaces: Store<Namespace>,
 }
 
 pub async fn run() -> Result<()> {
@@ -65,29 +103,88 @@ pub async fn run() -> Result<()> {
         }
     }
 
+    let config = Config::load().map_err(|e| e.context("loading configuration"))?;
+
     let client = Client::try_default()
         .await
         .map_err(|e| anyhow!(e).context("initializing Kubernetes client"))?;
     info!("Kubernetes client initialized");
     let apps = Api::<Application>::all(client.clone());
     let ingress_zones = Api::<IngressZone>::all(client.clone());
+    let config_maps = Api::<ConfigMap>::all(client.clone());
+    let secrets = Api::<Secret>::all(client.clone());
+
+    let (namespaces, namespace_writer) = reflector::store();
+    let namespace_reflector = reflector::reflector(
+        namespace_writer,
+        watcher(Api::<Namespace>::all(client.clone()), Default::default()),
+    )
+    .default_backoff()
+    .for_each(|_| futures::future::ready(()));
+
+    // Registries are contacted during reconciliation, so don't let a slow one hold it up
+    let http = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| anyhow!(e).context("initializing HTTP client"))?;
 
+    let app_controller = Controller::new(apps.clone(), Default::default());
     let ctx = Arc::new(Context {
         client,
+        config,
+        http,
         ingress_zones: RwLock::new(HashMap::new()),
+        apps: app_controller.store(),
+        namespaces,
     });
-
-    let app_controller = Controller::new(apps.clone(), Default::default())
+    let config_map_apps = app_controller.store();
+    let config_map_ctx = ctx.clone();
+    let secret_apps = app_controller.store();
+    let secret_ctx = ctx.clone();
+    let app_controller = app_controller
+        .watches(config_maps, Default::default(), move |cm: ConfigMap| {
+            config_sources::applications_using(
+                &config_map_ctx.config,
+                config_map_apps.state(),
+                SourceKind::ConfigMap,
+                cm.namespace(),
+                &cm.name_any(),
+            )
+        })
+        .watches(secrets, Default::default(), move |secret: Secret| {
+            config_sources::applications_using(
+                &secret_ctx.config,
+                secret_apps.state(),
+                SourceKind::Secret,
+                secret.namespace(),
+                &secret.name_any(),
+            )
+        })
         .run(reconcile_apps, error_policy, ctx.clone())
         .for_each(|_| futures::future::ready(()));
     info!("Application controller created");
+    // Update the zones an application starts or stops using, remembering the zones it used before
+    let app_zones: Mutex<HashMap<ObjectRef<Application>, BTreeSet<String>>> = Default::default();
     let zone_controller = Controller::new(ingress_zones.clone(), Default::default())
+        .watches(apps, Default::default(), move |app: Application| {
+            let zones = zone_access::referenced_zones(&app);
+            let previous = app_zones
+                .lock()
+                .unwrap()
+                .insert(ObjectRef::from_obj(&app), zones.clone())
+                .unwrap_or_default();
+            zones
+                .union(&previous)
+                .map(|zone| ObjectRef::new(zone))
+                .collect::<Vec<_>>()
+        })
         .run(reconcile_zones, error_policy, ctx.clone())
         .for_each(|_| futures::future::ready(()));
     info!("Zone controller created");
 
     info!("Starting controllers");
-    join!(app_controller, zone_controller);
+    join!(app_controller, zone_controller, namespace_reflector);
 
     warn!("Controller terminated unexpectedly");
 
@@ -102,6 +199,19 @@ async fn reconcile_zones(obj: Arc<IngressZone>, ctx: Arc<Context>) -> ReconcileR
     let zone_name = obj.metadata.name.as_ref().unwrap().clone();
     info!("reconcile request received for zone {}", zone_name);
     zones.insert(zone_name, obj.clone());
+    drop(zones);
+    // Consumers are computed from the caches, which are empty until the initial listing is done
+    if ctx.apps.wait_until_ready().await.is_err()
+        || ctx.namespaces.wait_until_ready().await.is_err()
+    {
+        return Err(ReconcilerError::ZoneStatus);
+    }
+    zone_status::update_status(&ctx.client, &obj, &ctx.config, &ctx.apps, &ctx.namespaces)
+        .await
+        .map_err(|e| {
+            error!("Error updating ingress zone status: {:?}", e);
+            ReconcilerError::ZoneStatus
+        })?;
     Ok(Action::requeue(Duration::from_secs(3600)))
 }
 
@@ -110,10 +220,215 @@ async fn reconcile_apps(obj: Arc<Application>, ctx: Arc<Context>) -> ReconcileRe
     let trace_id = get_trace_id();
     Span::current().record("trace_id", field::display(&trace_id));
 
+    info!("reconcile request received");
+    let config_hash = config_sources::compute_hash(&ctx.client, &obj, &ctx.config)
+        .await
+        .map_err(|e| {
+            error!("Error computing configuration hash: {:?}", e);
+            ReconcilerError::ConfigHash
+        })?;
+    let stale_config_maps = inline_files::find_stale_config_maps(&ctx.client, &obj)
+        .await
+        .map_err(|e| {
+            error!("Error finding stale inline files configmaps: {:?}", e);
+            ReconcilerError::StaleConfigMaps
+        })?;
+    let workloads = workloads::find_workloads(&ctx.client, &obj)
+        .await
+        .map_err(|e| {
+            error!("Error finding application workloads: {:?}", e);
+            ReconcilerError::WorkloadLookup
+        })?;
+    // Use the same cached namespaces as the zone status, once the initial listing is done
+    ctx.namespaces.wait_until_ready().await.map_err(|e| {
+        error!("Error waiting for the namespace cache: {:?}", e);
+        ReconcilerError::NamespaceLookup
+    })?;
+    let namespace_labels = ctx
+        .namespaces
+        .get(&ObjectRef::new(
+            &obj.namespace().unwrap_or("default".to_string()),
+        ))
+        .map(|ns| ns.labels().clone())
+        .unwrap_or_default();
+    let service_cluster_ip = Api::<Service>::namespaced(
+        ctx.client.clone(),
+        &obj.namespace().unwrap_or("default".to_string()),
+    )
+    .get_opt(&obj.name_any())
+    .await
+    .map_err(|e| {
+        error!("Error getting application service: {:?}", e);
+        ReconcilerError::ServiceLookup
+    })?
+    .and_then(|service| service.spec)
+    .and_then(|spec| spec.cluster_ip);
+    let http_route = resource_creator::http_route_resource();
+    let gateway_api = ctx
+        .client
+        .list_api_group_resources(&http_route.api_version)
+        .await
+        .map(|resources| {
+            resources
+                .resources
+                .iter()
+                .any(|resource| resource.name == http_route.plural)
+        })
+        .or_else(|e| match e {
+            kube::Error::Api(response) if response.code == 404 => Ok(false),
+            e => Err(e),
+        })
+        .map_err(|e| {
+            error!("Error discovering the Gateway API: {:?}", e);
+            ReconcilerError::GatewayApiLookup
+        })?;
+    let mut cluster_state = ClusterState {
+        config_hash,
+        stale_config_maps: stale_config_maps.unused,
+        namespace_labels,
+        resolved_image: None,
+        workloads: workloads.ready,
+        service_cluster_ip,
+        gateway_api,
+        host_conflicts: BTreeMap::new(),
+        existing_stateful_set: workloads.stateful_set,
+    };
+    // Check back soon to clean up configmaps still in use by a rollout in progress,
+    // or a workload replaced when switching between Deployment and StatefulSet
+    let requeue_after = if stale_config_maps.in_use
+        || resource_creator::keep_replaced_workload(&obj, &cluster_state)
+    {
+        Duration::from_secs(30)
+    } else {
+        Duration::from_secs(3600)
+    };
+
     let zones = ctx.ingress_zones.read().await;
+    let denied_zones = zone_access::denied_zones(&obj, &zones, &cluster_state);
+    ctx.apps.wait_until_ready().await.map_err(|e| {
+        error!("Error waiting for the application cache: {:?}", e);
+        ReconcilerError::HostConflicts
+    })?;
+    cluster_state.host_conflicts = zone_status::host_conflicts(
+        &obj,
+        &zones,
+        &ctx.apps.state(),
+        &ctx.namespaces,
+        &ctx.config,
+    );
+    let hosts_condition = if cluster_state.host_conflicts.is_empty() {
+        status::condition(obj.as_ref(), "HostsAvailable", true, "HostsAvailable", "")
+    } else {
+        let conflicts: Vec<String> = cluster_state
+            .host_conflicts
+            .iter()
+            .map(|(zone, hosts)| format!("{} in zone {}", hosts.join(", "), zone))
+            .collect();
+        status::condition(
+            obj.as_ref(),
+            "HostsAvailable",
+            false,
+            "HostConflict",
+            &format!(
+                "Hosts are already used by other applications: {}",
+                conflicts.join("; ")
+            ),
+        )
+    };
+    let ingress_condition = if denied_zones.is_empty() {
+        status::condition(obj.as_ref(), "IngressAllowed", true, "ZonesAllowed", "")
+    } else {
+        status::condition(
+            obj.as_ref(),
+            "IngressAllowed",
+            false,
+            "NamespaceNotAllowed",
+            &format!(
+                "Namespace is not allowed to use ingress zones: {}",
+                denied_zones.join(", ")
+            ),
+        )
+    };
+    let registry_check = image::check_registry(&obj.spec.image, &ctx.config);
+    let image_condition = match &registry_check {
+        Ok(()) => status::condition(obj.as_ref(), "ImageAllowed", true, "RegistryAllowed", ""),
+        Err(e) => status::condition(
+            obj.as_ref(),
+            "ImageAllowed",
+            false,
+            "RegistryNotAllowed",
+            &e.to_string(),
+        ),
+    };
+    let immutable_changes = resource_creator::immutable_stateful_set_changes(&obj, &cluster_state);
+    let workload_condition = if immutable_changes.is_empty() {
+        status::condition(obj.as_ref(), "WorkloadUpdated", true, "Updated", "")
+    } else {
+        status::condition(
+            obj.as_ref(),
+            "WorkloadUpdated",
+            false,
+            "ImmutableFieldsChanged",
+            &format!(
+                "The {} of the existing StatefulSet can't be changed. Delete the StatefulSet with \
+                 --cascade=orphan to recreate it, and resize existing claims separately",
+                immutable_changes.join(" and ")
+            ),
+        )
+    };
+    let mut conditions = vec![
+        ingress_condition,
+        hosts_condition,
+        image_condition,
+        workload_condition,
+    ];
+    // Only resolve images from allowed registries
+    let resolution = if registry_check.is_ok() {
+        let resolution = image::resolved_image(&ctx.http, &ctx.client, &obj, &ctx.config).await;
+        conditions.push(match &resolution {
+            Ok(Some(_)) => {
+                status::condition(obj.as_ref(), "ImageResolved", true, "DigestResolved", "")
+            }
+            Ok(None) => status::condition(obj.as_ref(), "ImageResolved", true, "NotPinned", ""),
+            Err(e) => status::condition(
+                obj.as_ref(),
+                "ImageResolved",
+                false,
+                "ResolutionFailed",
+                &format!("{:#}", e),
+            ),
+        });
+        resolution
+    } else {
+        Ok(None)
+    };
+    status::update_conditions(&ctx.client, &obj, conditions)
+        .await
+        .map_err(|e| {
+            error!("Error updating application status: {:?}", e);
+            ReconcilerError::UpdateStatus
+        })?;
+    // Leave existing resources alone until the image is fixed, retrying when the application changes
+    if let Err(e) = registry_check {
+        warn!("Not reconciling application: {:?}", e);
+        return Ok(Action::requeue(Duration::from_secs(3600)));
+    }
+    // The registry may be unavailable for a while, so check back soon
+    cluster_state.resolved_image = match resolution {
+        Ok(resolved_image) => resolved_image,
+        Err(e) => {
+            warn!("Not reconciling application: {:?}", e);
+            return Ok(Action::requeue(Duration::from_secs(60)));
+        }
+    };
+    status::update_resolved_image(&ctx.client, &obj, cluster_state.resolved_image.clone())
+        .await
+        .map_err(|e| {
+            error!("Error updating application status: {:?}", e);
+            ReconcilerError::UpdateStatus
+        })?;
 
-    info!("reconcile request received");
-    match resource_creator::process(obj, &zones) {
+    match resource_creator::process(obj, &zones, &ctx.config, &cluster_state) {
         Err(e) => {
             error!("Error processing resource: {:?}", e);
             return Err(ReconcilerError::ResourceProcessing);
@@ -141,7 +456,7 @@ async fn reconcile_apps(obj: Arc<Application>, ctx: Arc<Context>) -> ReconcileRe
             }
         }
     };
-    Ok(Action::requeue(Duration::from_secs(3600)))
+    Ok(Action::requeue(requeue_after))
 }
 
 fn error_policy<T>(_object: Arc<T>, err: &ReconcilerError, _ctx: Arc<Context>) -> Action {
@@ -19,6 +19,8 @@ use tracing::log::{debug, info};
 #[serde(tag = "operation", content = "object")]
 pub enum Operation {
     CreateOrUpdate(Arc<DynamicObject>),
+    /// Create the object, leaving it untouched if it already exists.
+    CreateIfMissing(Arc<DynamicObject>),
     DeleteIfExists(Arc<DynamicObject>),
 }
 
@@ -26,6 +28,7 @@ impl Display for Operation {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             Operation::CreateOrUpdate(_obj) => write!(f, "CreateOrUpdate"),
+            Operation::CreateIfMissing(_obj) => write!(f, "CreateIfMissing"),
             Operation::DeleteIfExists(_obj) => write!(f, "DeleteIfExists"),
         }
     }
@@ -36,7 +39,11 @@ impl Operation {
     pub async fn apply(&self, client: Client) -> Result<Arc<DynamicObject>> {
         match self {
             Operation::CreateOrUpdate(object) => {
-                self.apply_create_or_update(client, object).await?;
+                self.apply_create_or_update(client, object, true).await?;
+                Ok(object.clone())
+            }
+            Operation::CreateIfMissing(object) => {
+                self.apply_create_or_update(client, object, false).await?;
                 Ok(object.clone())
             }
             Operation::DeleteIfExists(object) => {
@@ -61,6 +68,7 @@ impl Operation {
         &self,
         client: Client,
         object: &Arc<DynamicObject>,
+        replace: bool,
     ) -> Result<()> {
         let discovery = Discovery::new(client.clone())
             .run()
@@ -77,6 +85,9 @@ impl Operation {
         let object_name = object.metadata.name.clone().unwrap();
         let existing = api.get(&object_name).await;
         match existing {
+            Ok(_) if !replace => {
+                debug!("{} {:?} already exists, leaving it", gvk.kind, object_name);
+            }
             Ok(existing_obj) => {
                 debug!("{} {:?} already exists", gvk.kind, object_name);
                 let mut obj = object.deref().clone();
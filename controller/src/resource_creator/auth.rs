@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use kube::ResourceExt;
+use serde_json::{json, Value};
+
+use api::application::{AuthMode, Ingress};
+use api::ingress_zone::v1::IngressZone;
+use api::ingress_zone::{IngressController, ZoneAuth};
+
+const OAUTH2_PROXY_RESPONSE_HEADERS: [&str; 3] = [
+    "X-Auth-Request-User",
+    "X-Auth-Request-Email",
+    "X-Auth-Request-Preferred-Username",
+];
+
+/// The auth mode of the ingress, falling back to the default of the zone.
+fn auth_mode(zone: &IngressZone, ingress: &Ingress) -> AuthMode {
+    ingress
+        .auth
+        .or_else(|| zone.spec.auth.as_ref().and_then(|auth| auth.default_mode))
+        .unwrap_or_default()
+}
+
+/// The auth provider of the zone, if the ingress needs one.
+fn zone_auth<'a>(
+    zone: &'a IngressZone,
+    ingress: &Ingress,
+) -> Result<Option<(&'a ZoneAuth, AuthMode)>> {
+    let mode = auth_mode(zone, ingress);
+    if mode == AuthMode::None {
+        return Ok(None);
+    }
+    let auth = zone.spec.auth.as_ref().ok_or_else(|| {
+        anyhow!(
+            "auth {} requested, but ingress zone {} has no auth provider",
+            mode,
+            zone.name_any()
+        )
+    })?;
+    Ok(Some((auth, mode)))
+}
+
+/// Annotations for the ingress controller of the zone, implementing the auth mode of the ingress.
+pub(crate) fn auth_annotations(
+    zone: &IngressZone,
+    ingress: &Ingress,
+) -> Result<BTreeMap<String, String>> {
+    let Some((auth, mode)) = zone_auth(zone, ingress)? else {
+        return Ok(BTreeMap::new());
+    };
+    let (url, signin_url, response_headers) = match (&auth.oauth2_proxy_url, &auth.url) {
+        (Some(base), None) => {
+            let base = base.trim_end_matches('/');
+            let url = match mode {
+                AuthMode::Optional => bail!(
+                    "ingress zone {} uses oauth2-proxy, which does not support optional auth",
+                    zone.name_any()
+                ),
+                _ => format!("{}/oauth2/auth", base),
+            };
+            let signin_url = format!(
+                "{}/oauth2/start?rd=$scheme://$host$escaped_request_uri",
+                base
+            );
+            let headers = OAUTH2_PROXY_RESPONSE_HEADERS.map(String::from).to_vec();
+            (url, Some(signin_url), headers)
+        }
+        (None, Some(url)) => {
+            let url = match mode {
+                AuthMode::Optional => auth.optional_url.clone().ok_or_else(|| {
+                    anyhow!(
+                        "ingress zone {} has no optionalUrl, optional auth is not supported",
+                        zone.name_any()
+                    )
+                })?,
+                _ => url.clone(),
+            };
+            (url, auth.signin_url.clone(), auth.response_headers.clone())
+        }
+        _ => bail!(
+            "auth in ingress zone {} must have exactly one of oauth2ProxyUrl and url",
+            zone.name_any()
+        ),
+    };
+
+    let mut annotations = BTreeMap::new();
+    match zone.spec.ingress_controller {
+        Some(IngressController::Nginx) => {
+            const PREFIX: &str = "nginx.ingress.kubernetes.io";
+            annotations.insert(format!("{}/auth-url", PREFIX), url);
+            if mode == AuthMode::Required {
+                if let Some(signin_url) = signin_url {
+                    annotations.insert(format!("{}/auth-signin", PREFIX), signin_url);
+                }
+            }
+            if !response_headers.is_empty() {
+                annotations.insert(
+                    format!("{}/auth-response-headers", PREFIX),
+                    response_headers.join(","),
+                );
+            }
+        }
+        None => bail!(
+            "ingress zone {} has no ingressController, auth is not supported",
+            zone.name_any()
+        ),
+    }
+    Ok(annotations)
+}
+
+/// HTTPRoute filters implementing the auth mode of the ingress in a gateway zone.
+pub(crate) fn gateway_auth_filters(zone: &IngressZone, ingress: &Ingress) -> Result<Vec<Value>> {
+    let Some((auth, mode)) = zone_auth(zone, ingress)? else {
+        return Ok(vec![]);
+    };
+    if mode == AuthMode::Optional {
+        bail!(
+            "optional auth is not supported in gateway zone {}",
+            zone.name_any()
+        );
+    }
+    let filter = auth.gateway_filter.as_ref().ok_or_else(|| {
+        anyhow!(
+            "gateway zone {} has no gatewayFilter, auth is not supported",
+            zone.name_any()
+        )
+    })?;
+    Ok(vec![json!({
+        "type": "ExtensionRef",
+        "extensionRef": {
+            "group": filter.group,
+            "kind": filter.kind,
+            "name": filter.name,
+        },
+    })])
+}
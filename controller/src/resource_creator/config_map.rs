@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::Result;
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::ResourceExt;
+use tracing::instrument;
+
+use api::application::v1::Application;
+
+use crate::inline_files::{config_map_name, INLINE_FILES_LABEL};
+use crate::models::Operation;
+use crate::resource_creator::{to_dynamic_object, ClusterState};
+
+#[instrument(skip(app, object_meta, cluster_state), fields(trace_id))]
+pub(crate) fn process(
+    app: &Arc<Application>,
+    object_meta: ObjectMeta,
+    cluster_state: &ClusterState,
+) -> Result<Vec<Operation>> {
+    let mut operations = Vec::new();
+    if let Some(name) = config_map_name(app) {
+        let mut metadata = object_meta.clone();
+        metadata.name = Some(name);
+        metadata
+            .labels
+            .get_or_insert_with(BTreeMap::new)
+            .insert(INLINE_FILES_LABEL.to_string(), app.name_any());
+        let config_map = ConfigMap {
+            metadata,
+            data: Some(app.spec.files.clone()),
+            immutable: Some(true),
+            ..Default::default()
+        };
+        operations.push(Operation::CreateOrUpdate(Arc::new(to_dynamic_object(
+            config_map,
+        )?)));
+    }
+
+    for name in cluster_state.stale_config_maps.iter() {
+        let config_map = ConfigMap {
+            metadata: ObjectMeta {
+                name: Some(name.clone()),
+                namespace: object_meta.namespace.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        operations.push(Operation::DeleteIfExists(Arc::new(to_dynamic_object(
+            config_map,
+        )?)));
+    }
+    Ok(operations)
+}
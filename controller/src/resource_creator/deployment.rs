@@ -1,25 +1,57 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use k8s_openapi::api::apps::v1::{Deployment, DeploymentSpec};
+use k8s_openapi::api::apps::v1::{
+    Deployment, DeploymentSpec, DeploymentStrategy, RollingUpdateDeployment,
+    RollingUpdateStatefulSetStrategy, StatefulSet, StatefulSetSpec, StatefulSetUpdateStrategy,
+};
 use k8s_openapi::api::core::v1::{
     Affinity, ConfigMapEnvSource, ConfigMapVolumeSource, Container, ContainerPort, EnvFromSource,
-    HTTPGetAction, PodAffinityTerm, PodAntiAffinity, PodSpec, PodTemplateSpec, SecretEnvSource,
-    SecretVolumeSource, TCPSocketAction, Volume, VolumeMount, WeightedPodAffinityTerm,
+    EnvVar, EnvVarSource, HTTPGetAction, KeyToPath, Lifecycle, LifecycleHandler,
+    LocalObjectReference, ObjectFieldSelector, PersistentVolumeClaim,
+    PersistentVolumeClaimVolumeSource, PodAffinityTerm, PodAntiAffinity, PodSpec, PodTemplateSpec,
+    ProjectedVolumeSource, SecretEnvSource, SecretVolumeSource, ServiceAccountTokenProjection,
+    SleepAction, TCPSocketAction, Toleration, TopologySpreadConstraint, Volume, VolumeMount,
+    VolumeProjection, WeightedPodAffinityTerm,
 };
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
 use kube::ResourceExt;
 use tracing::instrument;
 
+use crate::config::{Config, DEFAULT_CONFIGMAP_MOUNT_PATH, DEFAULT_SECRET_MOUNT_PATH};
+use crate::config_sources::{implicit_sources, SourceKind};
+use crate::inline_files::config_map_name;
 use crate::models::Operation;
-use crate::resource_creator::to_dynamic_object;
-use anyhow::Result;
+use crate::resource_creator::persistent_volume_claim::{
+    generate_persistent_volume_claim_spec, persistent_volume_claim_name,
+};
+use crate::resource_creator::service_account::service_account_name;
+use crate::resource_creator::{
+    headless_service_name, is_stateful, keep_replaced_workload, replicas, to_dynamic_object,
+    ClusterState, ExistingStatefulSet,
+};
+use anyhow::{bail, Result};
 use api::application::v1::Application;
-use api::application::{Probe, Probes};
+use api::application::{
+    FilesFrom, FilesFromConfigMap, FilesFromItem, Probe, Probes, ProjectedToken, Rollout,
+    RolloutStrategy,
+};
 use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 
-const DEFAULT_SECRET_MOUNT_PATH: &str = "/var/run/secrets/yakup.ibidem.no";
-const DEFAULT_CONFIGMAP_MOUNT_PATH: &str = "/var/run/config/yakup.ibidem.no";
+const CONFIG_HASH_ANNOTATION: &str = "yakup.ibidem.no/config-hash";
+const PROJECTED_TOKEN_VOLUME: &str = "projected-token";
+/// Platform env referenced by other platform env, which applications can not override.
+const POD_NAME_REFERENCE_ENV: &str = "YAKUP_POD_NAME";
+const DEFAULT_PRE_STOP_SLEEP_SECONDS: i64 = 5;
+const DEFAULT_TERMINATION_GRACE_PERIOD_SECONDS: i64 = 30;
+
+struct PodScheduling {
+    affinity: Option<Affinity>,
+    node_selector: Option<BTreeMap<String, String>>,
+    tolerations: Option<Vec<Toleration>>,
+    topology_spread_constraints: Option<Vec<TopologySpreadConstraint>>,
+    priority_class_name: Option<String>,
+}
 
 struct FromConfig {
     env_from: Option<Vec<EnvFromSource>>,
@@ -27,100 +59,474 @@ struct FromConfig {
     volumes: Option<Vec<Volume>>,
 }
 
-#[instrument(skip(app, object_meta), fields(trace_id))]
+#[instrument(skip(app, object_meta, config, cluster_state), fields(trace_id))]
 pub(crate) fn process(
     app: &Arc<Application>,
     object_meta: ObjectMeta,
     labels: BTreeMap<String, String>,
+    config: &Config,
+    cluster_state: &ClusterState,
 ) -> Result<Vec<Operation>> {
-    let from_config = generate_from_config(app);
+    let from_config = generate_from_config(app, config)?;
+    let namespace = object_meta.namespace.clone().unwrap_or_default();
+    let env = generate_env(app, namespace.as_str(), config)?;
 
-    // Default to 2 replicas for HTTP applications, 1 for others
-    let mut replicas = 1;
-    if let Some(ports) = &app.spec.ports {
-        if let Some(http_port) = &ports.http {
-            if !http_port.ingress.is_empty() {
-                replicas = 2 // Default to 2 replicas for HTTP applications with ingress
+    let replicas = replicas(app);
+    let scheduling = generate_scheduling(app, config, &labels);
+    let rollout = app.spec.rollout.clone().unwrap_or_default();
+    let pre_stop_sleep_seconds = pre_stop_sleep_seconds(&rollout)?;
+
+    let selector = LabelSelector {
+        match_labels: Some(labels.clone()),
+        ..Default::default()
+    };
+    let template = PodTemplateSpec {
+        metadata: Some(ObjectMeta {
+            labels: Some(generate_pod_labels(app, &object_meta, &labels)?),
+            annotations: generate_pod_annotations(app, &object_meta, cluster_state),
+            ..Default::default()
+        }),
+        spec: Some(PodSpec {
+            affinity: scheduling.affinity,
+            node_selector: scheduling.node_selector,
+            tolerations: scheduling.tolerations,
+            topology_spread_constraints: scheduling.topology_spread_constraints,
+            priority_class_name: scheduling.priority_class_name,
+            termination_grace_period_seconds: rollout.termination_grace_period_seconds,
+            service_account_name: Some(service_account_name(app)),
+            automount_service_account_token: app
+                .spec
+                .service_account
+                .as_ref()
+                .and_then(|options| options.automount_token),
+            containers: vec![Container {
+                name: app.name_any().clone(),
+                image: Some(
+                    cluster_state
+                        .resolved_image
+                        .clone()
+                        .unwrap_or(app.spec.image.clone()),
+                ),
+                image_pull_policy: app.spec.image_pull_policy.map(|policy| policy.to_string()),
+                ports: generate_ports(app),
+                env: Some(env),
+                env_from: from_config.env_from,
+                volume_mounts: from_config.volume_mounts,
+                liveness_probe: generate_probe(app, |probes: &Probes| probes.liveness.clone()),
+                readiness_probe: generate_probe(app, |probes: &Probes| probes.readiness.clone()),
+                startup_probe: generate_probe(app, |probes: &Probes| probes.startup.clone()),
+                resources: app.spec.resources.clone(),
+                lifecycle: generate_lifecycle(pre_stop_sleep_seconds),
+                ..Default::default()
+            }],
+            volumes: from_config.volumes,
+            image_pull_secrets: generate_image_pull_secrets(app, config),
+            ..Default::default()
+        }),
+    };
+
+    // Create the new workload, and delete the old one once the new is ready when switching between kinds
+    let keep_replaced = keep_replaced_workload(app, cluster_state);
+    let removed_meta = ObjectMeta {
+        name: object_meta.name.clone(),
+        namespace: object_meta.namespace.clone(),
+        ..Default::default()
+    };
+    if is_stateful(app) {
+        let mut service_name = Some(headless_service_name(app));
+        let mut volume_claim_templates = generate_volume_claim_templates(app);
+        // Changing these is rejected by the API server, and reported in the status instead
+        if let Some(existing) = &cluster_state.existing_stateful_set {
+            if !immutable_changes(app, existing).is_empty() {
+                service_name = existing.service_name.clone();
+                volume_claim_templates = Some(existing.volume_claim_templates.clone())
+                    .filter(|templates| !templates.is_empty());
             }
         }
+        let stateful_set = StatefulSet {
+            metadata: object_meta,
+            spec: Some(StatefulSetSpec {
+                replicas: Some(replicas),
+                selector,
+                service_name,
+                template,
+                volume_claim_templates,
+                update_strategy: generate_stateful_set_update_strategy(&rollout)?,
+                revision_history_limit: rollout.revision_history_limit,
+                min_ready_seconds: rollout.min_ready_seconds,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let removed_deployment = Deployment {
+            metadata: removed_meta,
+            ..Default::default()
+        };
+        let mut operations = vec![Operation::CreateOrUpdate(Arc::new(to_dynamic_object(
+            stateful_set,
+        )?))];
+        if !keep_replaced {
+            operations.push(Operation::DeleteIfExists(Arc::new(to_dynamic_object(
+                removed_deployment,
+            )?)));
+        }
+        Ok(operations)
+    } else {
+        let deployment = Deployment {
+            metadata: object_meta,
+            spec: Some(DeploymentSpec {
+                replicas: Some(replicas),
+                selector,
+                template,
+                strategy: generate_deployment_strategy(&rollout),
+                revision_history_limit: rollout.revision_history_limit,
+                progress_deadline_seconds: rollout.progress_deadline_seconds,
+                min_ready_seconds: rollout.min_ready_seconds,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let removed_stateful_set = StatefulSet {
+            metadata: removed_meta,
+            ..Default::default()
+        };
+        let mut operations = vec![Operation::CreateOrUpdate(Arc::new(to_dynamic_object(
+            deployment,
+        )?))];
+        if !keep_replaced {
+            operations.push(Operation::DeleteIfExists(Arc::new(to_dynamic_object(
+                removed_stateful_set,
+            )?)));
+        }
+        Ok(operations)
+    }
+}
+
+fn generate_deployment_strategy(rollout: &Rollout) -> Option<DeploymentStrategy> {
+    let strategy = rollout.strategy.clone().unwrap_or_default();
+    let rolling_update = match strategy {
+        RolloutStrategy::RollingUpdate
+            if rollout.max_surge.is_some() || rollout.max_unavailable.is_some() =>
+        {
+            Some(RollingUpdateDeployment {
+                max_surge: rollout.max_surge.clone(),
+                max_unavailable: rollout.max_unavailable.clone(),
+            })
+        }
+        _ => None,
+    };
+    Some(DeploymentStrategy {
+        rolling_update,
+        type_: Some(strategy.to_string()),
+    })
+}
+
+/// StatefulSets replace pods one at a time, and have no Recreate strategy.
+fn generate_stateful_set_update_strategy(
+    rollout: &Rollout,
+) -> Result<Option<StatefulSetUpdateStrategy>> {
+    if rollout.strategy == Some(RolloutStrategy::Recreate) {
+        bail!("the Recreate rollout strategy is not supported for applications with per-replica volumes");
+    }
+    if rollout.max_surge.is_some() {
+        bail!("maxSurge is not supported for applications with per-replica volumes");
+    }
+    Ok(rollout
+        .max_unavailable
+        .as_ref()
+        .map(|max_unavailable| StatefulSetUpdateStrategy {
+            rolling_update: Some(RollingUpdateStatefulSetStrategy {
+                max_unavailable: Some(max_unavailable.clone()),
+                partition: None,
+            }),
+            type_: Some(RolloutStrategy::RollingUpdate.to_string()),
+        }))
+}
+
+/// The default pull secrets from the controller configuration come first, followed by those of the application.
+fn generate_image_pull_secrets(
+    app: &Arc<Application>,
+    config: &Config,
+) -> Option<Vec<LocalObjectReference>> {
+    let mut names: Vec<&String> = config.image_pull_secrets.iter().collect();
+    for name in app.spec.image_pull_secrets.iter() {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    if names.is_empty() {
+        return None;
+    }
+    Some(
+        names
+            .into_iter()
+            .map(|name| LocalObjectReference { name: name.clone() })
+            .collect(),
+    )
+}
+
+/// The preStop sleep, which must leave time for the application to stop within the grace period.
+fn pre_stop_sleep_seconds(rollout: &Rollout) -> Result<i64> {
+    let pre_stop_sleep_seconds = rollout
+        .pre_stop_sleep_seconds
+        .unwrap_or(DEFAULT_PRE_STOP_SLEEP_SECONDS);
+    let termination_grace_period_seconds = rollout
+        .termination_grace_period_seconds
+        .unwrap_or(DEFAULT_TERMINATION_GRACE_PERIOD_SECONDS);
+    if pre_stop_sleep_seconds < 0 {
+        bail!("preStopSleepSeconds must not be negative");
+    }
+    if termination_grace_period_seconds < 0 {
+        bail!("terminationGracePeriodSeconds must not be negative");
+    }
+    if pre_stop_sleep_seconds > 0 && pre_stop_sleep_seconds >= termination_grace_period_seconds {
+        bail!("preStopSleepSeconds must be less than terminationGracePeriodSeconds");
+    }
+    Ok(pre_stop_sleep_seconds)
+}
+
+fn generate_lifecycle(pre_stop_sleep_seconds: i64) -> Option<Lifecycle> {
+    if pre_stop_sleep_seconds <= 0 {
+        return None;
+    }
+    Some(Lifecycle {
+        pre_stop: Some(LifecycleHandler {
+            sleep: Some(SleepAction {
+                seconds: pre_stop_sleep_seconds,
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// The fields of the existing StatefulSet that differ from what the application needs.
+pub(crate) fn immutable_changes(
+    app: &Application,
+    existing: &ExistingStatefulSet,
+) -> Vec<&'static str> {
+    let mut changes = Vec::new();
+    if existing.service_name != Some(headless_service_name(app)) {
+        changes.push("serviceName");
+    }
+    let claim_templates = |templates: &[PersistentVolumeClaim]| -> Vec<_> {
+        templates
+            .iter()
+            .map(|template| {
+                let spec = template.spec.clone().unwrap_or_default();
+                (
+                    template.metadata.name.clone(),
+                    spec.access_modes,
+                    spec.storage_class_name,
+                    spec.resources.and_then(|resources| resources.requests),
+                )
+            })
+            .collect()
     };
+    if claim_templates(&generate_volume_claim_templates(app).unwrap_or_default())
+        != claim_templates(&existing.volume_claim_templates)
+    {
+        changes.push("volumeClaimTemplates");
+    }
+    changes
+}
 
-    let deployment = Deployment {
-        metadata: object_meta,
-        spec: Some(DeploymentSpec {
-            replicas: Some(replicas),
-            selector: LabelSelector {
-                match_labels: Some(labels.clone()),
+fn generate_volume_claim_templates(app: &Application) -> Option<Vec<PersistentVolumeClaim>> {
+    let templates: Vec<PersistentVolumeClaim> = app
+        .spec
+        .files_from
+        .iter()
+        .filter_map(|ff| ff.persistent_volume.as_ref())
+        .filter(|pv| pv.per_replica.unwrap_or(false))
+        .map(|pv| PersistentVolumeClaim {
+            metadata: ObjectMeta {
+                name: Some(pv.name.clone()),
                 ..Default::default()
             },
-            template: PodTemplateSpec {
-                metadata: Some(ObjectMeta {
-                    labels: Some(labels.clone()),
-                    ..Default::default()
-                }),
-                spec: Some(PodSpec {
-                    affinity: Some(Affinity {
-                        pod_anti_affinity: Some(PodAntiAffinity {
-                            preferred_during_scheduling_ignored_during_execution: Some(vec![
-                                WeightedPodAffinityTerm {
-                                    weight: 100,
-                                    pod_affinity_term: PodAffinityTerm {
-                                        label_selector: Some(LabelSelector {
-                                            match_labels: Some(labels.clone()),
-                                            ..Default::default()
-                                        }),
-                                        topology_key: "kubernetes.io/hostname".to_string(),
-                                        ..Default::default()
-                                    },
-                                },
-                            ]),
-                            required_during_scheduling_ignored_during_execution: None,
-                        }),
-                        ..Default::default()
-                    }),
-                    service_account_name: Some(app.name_any().clone()),
-                    containers: vec![Container {
-                        name: app.name_any().clone(),
-                        image: Some(app.spec.image.clone()),
-                        ports: generate_ports(app),
-                        env: Some(app.spec.env.iter().map(|e| e.to_kube()).collect()),
-                        env_from: from_config.env_from,
-                        volume_mounts: from_config.volume_mounts,
-                        liveness_probe: generate_probe(app, |probes: &Probes| {
-                            probes.liveness.clone()
-                        }),
-                        readiness_probe: generate_probe(app, |probes: &Probes| {
-                            probes.readiness.clone()
-                        }),
-                        startup_probe: generate_probe(app, |probes: &Probes| {
-                            probes.startup.clone()
-                        }),
-                        resources: app.spec.resources.clone(),
-                        ..Default::default()
-                    }],
-                    volumes: from_config.volumes,
-                    ..Default::default()
-                }),
-            },
+            spec: Some(generate_persistent_volume_claim_spec(pv)),
             ..Default::default()
-        }),
+        })
+        .collect();
+    if templates.is_empty() {
+        None
+    } else {
+        Some(templates)
+    }
+}
+
+/// Settings on the application override the defaults from the controller configuration.
+/// If neither configure affinity or topology spread, pods prefer to be placed on different nodes.
+fn generate_scheduling(
+    app: &Arc<Application>,
+    config: &Config,
+    labels: &BTreeMap<String, String>,
+) -> PodScheduling {
+    let defaults = &config.scheduling;
+    let overrides = app.spec.scheduling.clone().unwrap_or_default();
+    let label_selector = LabelSelector {
+        match_labels: Some(labels.clone()),
         ..Default::default()
     };
 
-    Ok(vec![Operation::CreateOrUpdate(Arc::new(
-        to_dynamic_object(deployment)?,
-    ))])
+    let mut node_selector = defaults.node_selector.clone();
+    node_selector.extend(overrides.node_selector);
+    let mut tolerations = defaults.tolerations.clone();
+    tolerations.extend(overrides.tolerations);
+
+    let topology_spread = overrides
+        .topology_spread
+        .or(defaults.topology_spread.clone());
+    let topology_spread_constraints = topology_spread.as_ref().map(|spread| {
+        spread
+            .topology_keys
+            .iter()
+            .map(|key| TopologySpreadConstraint {
+                label_selector: Some(label_selector.clone()),
+                max_skew: spread.max_skew,
+                topology_key: key.clone(),
+                when_unsatisfiable: spread.when_unsatisfiable.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    });
+
+    let affinity = overrides.affinity.or(defaults.affinity.clone());
+    let affinity = match (affinity, &topology_spread) {
+        (Some(affinity), _) => Some(affinity),
+        (None, Some(_)) => None,
+        (None, None) => Some(Affinity {
+            pod_anti_affinity: Some(PodAntiAffinity {
+                preferred_during_scheduling_ignored_during_execution: Some(vec![
+                    WeightedPodAffinityTerm {
+                        weight: 100,
+                        pod_affinity_term: PodAffinityTerm {
+                            label_selector: Some(label_selector),
+                            topology_key: "kubernetes.io/hostname".to_string(),
+                            ..Default::default()
+                        },
+                    },
+                ]),
+                required_during_scheduling_ignored_during_execution: None,
+            }),
+            ..Default::default()
+        }),
+    };
+
+    PodScheduling {
+        affinity,
+        node_selector: if node_selector.is_empty() {
+            None
+        } else {
+            Some(node_selector)
+        },
+        tolerations: if tolerations.is_empty() {
+            None
+        } else {
+            Some(tolerations)
+        },
+        topology_spread_constraints,
+        priority_class_name: overrides
+            .priority_class_name
+            .or(defaults.priority_class_name.clone()),
+    }
+}
+
+/// Explicitly configured env comes after the platform env, and replaces platform env with the same name.
+fn generate_env(app: &Arc<Application>, namespace: &str, config: &Config) -> Result<Vec<EnvVar>> {
+    let explicit = app
+        .spec
+        .env
+        .iter()
+        .map(|e| e.to_kube())
+        .collect::<Result<Vec<_>>>()?;
+    if explicit.iter().any(|e| e.name == POD_NAME_REFERENCE_ENV) {
+        bail!(
+            "{} is reserved and can not be set in env",
+            POD_NAME_REFERENCE_ENV
+        );
+    }
+    let mut env: Vec<EnvVar> = generate_platform_env(app, namespace, config)
+        .into_iter()
+        .filter(|platform| !explicit.iter().any(|e| e.name == platform.name))
+        .collect();
+    env.extend(explicit);
+    Ok(env)
+}
+
+fn generate_platform_env(app: &Arc<Application>, namespace: &str, config: &Config) -> Vec<EnvVar> {
+    let app_name = app.name_any();
+    let mut env = vec![
+        generate_literal_env("YAKUP_APP_NAME", app_name.as_str()),
+        generate_literal_env("YAKUP_NAMESPACE", namespace),
+        generate_field_env(POD_NAME_REFERENCE_ENV, "metadata.name"),
+        generate_field_env("POD_NAME", "metadata.name"),
+        generate_literal_env("OTEL_SERVICE_NAME", app_name.as_str()),
+        generate_literal_env(
+            "OTEL_RESOURCE_ATTRIBUTES",
+            format!(
+                "service.namespace={},k8s.namespace.name={},k8s.pod.name=$({})",
+                namespace, namespace, POD_NAME_REFERENCE_ENV
+            )
+            .as_str(),
+        ),
+    ];
+    if let Some(endpoint) = &config.otel_exporter_otlp_endpoint {
+        env.push(generate_literal_env(
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            endpoint,
+        ));
+    }
+    env
+}
+
+fn generate_field_env(name: &str, field_path: &str) -> EnvVar {
+    EnvVar {
+        name: name.to_string(),
+        value: None,
+        value_from: Some(EnvVarSource {
+            field_ref: Some(ObjectFieldSelector {
+                api_version: None,
+                field_path: field_path.to_string(),
+            }),
+            ..Default::default()
+        }),
+    }
+}
+
+fn generate_literal_env(name: &str, value: &str) -> EnvVar {
+    EnvVar {
+        name: name.to_string(),
+        value: Some(value.to_string()),
+        value_from: None,
+    }
 }
 
-fn generate_from_config(app: &Arc<Application>) -> FromConfig {
+fn generate_from_config(app: &Arc<Application>, config: &Config) -> Result<FromConfig> {
     let mut env_from = vec![];
     let mut volume_mounts = vec![];
     let mut volumes = vec![];
 
-    for name in [format!("{}-db", app.name_any()), app.name_any()].iter() {
-        env_from.extend(generate_env_from(name));
-        volume_mounts.extend(generate_volume_mounts(name));
-        volumes.extend(generate_volumes(name));
+    let implicit_config = &config.implicit_sources;
+    for source in implicit_sources(app, implicit_config).iter() {
+        let name = source.name.as_str();
+        match source.kind {
+            SourceKind::ConfigMap => {
+                env_from.push(generate_env_from_configmap(name));
+                volume_mounts.push(generate_volume_mounts_from_configmap(
+                    name,
+                    &format!("{}/{}", implicit_config.config_map_mount_root, name),
+                ));
+                volumes.push(generate_volume_for_configmap(name, Some(true)));
+            }
+            SourceKind::Secret => {
+                env_from.push(generate_env_from_secret(name));
+                volume_mounts.push(generate_volume_mounts_from_secret(
+                    name,
+                    &format!("{}/{}", implicit_config.secret_mount_root, name),
+                ));
+                volumes.push(generate_volume_for_secret(name, Some(true)));
+            }
+        }
     }
 
     for ef in app.spec.env_from.iter() {
@@ -132,31 +538,75 @@ fn generate_from_config(app: &Arc<Application>) -> FromConfig {
         }
     }
 
+    let mut files_from = app.spec.files_from.clone();
+    if let Some(name) = config_map_name(app) {
+        let mount_path = app.spec.files_mount_path.clone().unwrap_or(format!(
+            "{}/{}-files",
+            DEFAULT_CONFIGMAP_MOUNT_PATH,
+            app.name_any()
+        ));
+        files_from.push(FilesFrom {
+            config_map: Some(FilesFromConfigMap {
+                name,
+                mount_path: Some(mount_path),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    }
+
     let mut empty_dir_idx = 0;
-    for ff in app.spec.files_from.iter() {
+    for ff in files_from.iter() {
         if let Some(ffcm) = &ff.config_map {
             let name = ffcm.name.as_str();
-            let mount_path: String = match &ffcm.mount_path {
-                Some(mount_path) => mount_path.to_owned(),
-                None => format!("{}/{}", DEFAULT_CONFIGMAP_MOUNT_PATH, name),
-            };
-            volume_mounts.push(generate_volume_mounts_from_configmap(
+            let mount_path = generate_mount_path(
+                DEFAULT_CONFIGMAP_MOUNT_PATH,
                 name,
-                mount_path.as_str(),
-            ));
-            volumes.push(generate_volume_for_configmap(name, None));
+                &ffcm.mount_path,
+                &ffcm.sub_path,
+            );
+            let mut volume_mount = generate_volume_mounts_from_configmap(name, mount_path.as_str());
+            volume_mount.sub_path = ffcm.sub_path.clone();
+            volume_mounts.push(volume_mount);
+            let mut volume = generate_volume_for_configmap(name, ffcm.optional);
+            if let Some(source) = volume.config_map.as_mut() {
+                source.items = generate_items(&ffcm.items);
+                source.default_mode = ffcm.default_mode.or(source.default_mode);
+            }
+            push_volume(&mut volumes, volume)?;
         }
         if let Some(ffs) = &ff.secret {
             let name = ffs.name.as_str();
-            let mount_path: String = match &ffs.mount_path {
-                Some(mount_path) => mount_path.to_owned(),
-                None => format!("{}/{}", DEFAULT_SECRET_MOUNT_PATH, name),
-            };
-            volume_mounts.push(generate_volume_mounts_from_secret(
+            let mount_path = generate_mount_path(
+                DEFAULT_SECRET_MOUNT_PATH,
                 name,
-                mount_path.as_str(),
-            ));
-            volumes.push(generate_volume_for_secret(name, None));
+                &ffs.mount_path,
+                &ffs.sub_path,
+            );
+            let mut volume_mount = generate_volume_mounts_from_secret(name, mount_path.as_str());
+            volume_mount.sub_path = ffs.sub_path.clone();
+            volume_mounts.push(volume_mount);
+            let mut volume = generate_volume_for_secret(name, ffs.optional);
+            if let Some(source) = volume.secret.as_mut() {
+                source.items = generate_items(&ffs.items);
+                source.default_mode = ffs.default_mode.or(source.default_mode);
+            }
+            push_volume(&mut volumes, volume)?;
+        }
+        if let Some(ffpv) = &ff.persistent_volume {
+            let name = ffpv.name.clone();
+            let mount_path = ffpv.mount_path.as_str();
+            volume_mounts.push(generate_volume_mounts_from(name.clone(), mount_path, None));
+            // Per-replica volumes come from the volumeClaimTemplates of the StatefulSet
+            if !ffpv.per_replica.unwrap_or(false) {
+                push_volume(
+                    &mut volumes,
+                    generate_volume_for_persistent_volume_claim(
+                        name.as_str(),
+                        persistent_volume_claim_name(app, ffpv).as_str(),
+                    ),
+                )?;
+            }
         }
         if let Some(ffe) = &ff.empty_dir {
             let name = format!("emptydir-{}", empty_dir_idx);
@@ -167,11 +617,117 @@ fn generate_from_config(app: &Arc<Application>) -> FromConfig {
         }
     }
 
-    FromConfig {
+    if let Some(token) = app
+        .spec
+        .service_account
+        .as_ref()
+        .and_then(|options| options.projected_token.as_ref())
+    {
+        let mount_path = token.mount_path.clone().unwrap_or(format!(
+            "{}/{}",
+            DEFAULT_SECRET_MOUNT_PATH, PROJECTED_TOKEN_VOLUME
+        ));
+        volume_mounts.push(generate_volume_mounts_from(
+            PROJECTED_TOKEN_VOLUME.to_string(),
+            &mount_path,
+            Some(true),
+        ));
+        volumes.push(generate_volume_for_projected_token(token));
+    }
+
+    Ok(FromConfig {
         env_from: Some(env_from),
         volume_mounts: Some(volume_mounts),
         volumes: Some(volumes),
+    })
+}
+
+fn generate_volume_for_projected_token(token: &ProjectedToken) -> Volume {
+    Volume {
+        name: PROJECTED_TOKEN_VOLUME.to_string(),
+        projected: Some(ProjectedVolumeSource {
+            sources: Some(vec![VolumeProjection {
+                service_account_token: Some(ServiceAccountTokenProjection {
+                    audience: Some(token.audience.clone()),
+                    expiration_seconds: token.expiration_seconds,
+                    path: "token".to_string(),
+                }),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn generate_mount_path(
+    default_root: &str,
+    name: &str,
+    mount_path: &Option<String>,
+    sub_path: &Option<String>,
+) -> String {
+    match (mount_path, sub_path) {
+        (Some(mount_path), _) => mount_path.to_owned(),
+        (None, Some(sub_path)) => format!("{}/{}/{}", default_root, name, sub_path),
+        (None, None) => format!("{}/{}", default_root, name),
+    }
+}
+
+fn generate_items(items: &[FilesFromItem]) -> Option<Vec<KeyToPath>> {
+    if items.is_empty() {
+        None
+    } else {
+        Some(items.iter().map(|item| item.to_kube()).collect())
+    }
+}
+
+/// Mounting the same source more than once, for instance with different subPaths, shares one volume.
+fn push_volume(volumes: &mut Vec<Volume>, volume: Volume) -> Result<()> {
+    match volumes.iter().find(|v| v.name == volume.name) {
+        Some(existing) if existing == &volume => Ok(()),
+        Some(_) => bail!(
+            "volume {} is mounted more than once with different items, defaultMode or optional",
+            volume.name
+        ),
+        None => {
+            volumes.push(volume);
+            Ok(())
+        }
+    }
+}
+
+/// Pods get the labels of the workload and the pod labels of the application.
+/// The selector labels can not be overridden.
+fn generate_pod_labels(
+    app: &Arc<Application>,
+    object_meta: &ObjectMeta,
+    selector_labels: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>> {
+    if let Some(key) = app
+        .spec
+        .pod_labels
+        .keys()
+        .find(|key| selector_labels.contains_key(*key))
+    {
+        bail!("podLabels can not override the {} label", key);
+    }
+    let mut labels = object_meta.labels.clone().unwrap_or_default();
+    labels.extend(app.spec.pod_labels.clone());
+    labels.extend(selector_labels.clone());
+    Ok(labels)
+}
+
+fn generate_pod_annotations(
+    app: &Arc<Application>,
+    object_meta: &ObjectMeta,
+    cluster_state: &ClusterState,
+) -> Option<BTreeMap<String, String>> {
+    let mut annotations = object_meta.annotations.clone().unwrap_or_default();
+    annotations.extend(app.spec.pod_annotations.clone());
+    if let Some(hash) = &cluster_state.config_hash {
+        annotations.insert(CONFIG_HASH_ANNOTATION.to_string(), hash.clone());
     }
+    (!annotations.is_empty()).then_some(annotations)
 }
 
 fn generate_probe(
@@ -214,13 +770,6 @@ fn generate_probe(
     }
 }
 
-fn generate_volumes(app_name: &str) -> Vec<Volume> {
-    vec![
-        generate_volume_for_configmap(app_name, Some(true)),
-        generate_volume_for_secret(app_name, Some(true)),
-    ]
-}
-
 fn generate_volume_for_configmap(name: &str, optional: Option<bool>) -> Volume {
     Volume {
         name: format!("{}-configmap", name.to_owned()),
@@ -247,25 +796,23 @@ fn generate_volume_for_secret(name: &str, optional: Option<bool>) -> Volume {
     }
 }
 
-fn generate_volume_for_empty_dir(name: &str) -> Volume {
+fn generate_volume_for_persistent_volume_claim(name: &str, claim_name: &str) -> Volume {
     Volume {
         name: name.to_owned(),
-        empty_dir: Some(Default::default()),
+        persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+            claim_name: claim_name.to_owned(),
+            read_only: None,
+        }),
         ..Default::default()
     }
 }
 
-fn generate_volume_mounts(app_name: &str) -> Vec<VolumeMount> {
-    vec![
-        generate_volume_mounts_from_configmap(
-            app_name,
-            &format!("{}/{}", DEFAULT_CONFIGMAP_MOUNT_PATH, app_name.to_owned()),
-        ),
-        generate_volume_mounts_from_secret(
-            app_name,
-            &format!("{}/{}", DEFAULT_SECRET_MOUNT_PATH, app_name.to_owned()),
-        ),
-    ]
+fn generate_volume_for_empty_dir(name: &str) -> Volume {
+    Volume {
+        name: name.to_owned(),
+        empty_dir: Some(Default::default()),
+        ..Default::default()
+    }
 }
 
 fn generate_volume_mounts_from_configmap(name: &str, mount_path: &str) -> VolumeMount {
@@ -317,13 +864,6 @@ fn generate_env_from_secret(name: &str) -> EnvFromSource {
     }
 }
 
-fn generate_env_from(app_name: &str) -> Vec<EnvFromSource> {
-    vec![
-        generate_env_from_configmap(app_name),
-        generate_env_from_secret(app_name),
-    ]
-}
-
 fn generate_ports(app: &Arc<Application>) -> Option<Vec<ContainerPort>> {
     let mut container_ports = Vec::new();
     if let Some(ports) = &app.spec.ports {
@@ -342,6 +882,15 @@ fn generate_ports(app: &Arc<Application>) -> Option<Vec<ContainerPort>> {
                 ..Default::default()
             });
         }
+
+        if let Some(udp_port) = &ports.udp {
+            container_ports.push(ContainerPort {
+                name: Some("udp".to_string()),
+                container_port: udp_port.port as i32,
+                protocol: Some("UDP".to_string()),
+                ..Default::default()
+            });
+        }
     }
     if container_ports.is_empty() {
         None
@@ -349,3 +898,142 @@ fn generate_ports(app: &Arc<Application>) -> Option<Vec<ContainerPort>> {
         Some(container_ports)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use api::application::v1::ApplicationSpec;
+    use api::application::EnvValue;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn app(env: Vec<EnvValue>) -> Arc<Application> {
+        Arc::new(Application::new(
+            "test-app",
+            ApplicationSpec {
+                image: "busybox:latest".to_string(),
+                env,
+                ..Default::default()
+            },
+        ))
+    }
+
+    fn literal(name: &str, value: &str) -> EnvValue {
+        EnvValue {
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            value_from: None,
+        }
+    }
+
+    #[test]
+    fn platform_env_references_reserved_pod_name() {
+        let config = Config {
+            otel_exporter_otlp_endpoint: Some("http://collector:4317".to_string()),
+            ..Default::default()
+        };
+        let env = generate_platform_env(&app(vec![]), "shop", &config);
+        let names: Vec<&str> = env.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "YAKUP_APP_NAME",
+                "YAKUP_NAMESPACE",
+                "YAKUP_POD_NAME",
+                "POD_NAME",
+                "OTEL_SERVICE_NAME",
+                "OTEL_RESOURCE_ATTRIBUTES",
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+            ]
+        );
+        let attributes = env
+            .iter()
+            .find(|e| e.name == "OTEL_RESOURCE_ATTRIBUTES")
+            .and_then(|e| e.value.clone());
+        assert_eq!(
+            attributes.as_deref(),
+            Some("service.namespace=shop,k8s.namespace.name=shop,k8s.pod.name=$(YAKUP_POD_NAME)")
+        );
+    }
+
+    #[test]
+    fn explicit_env_overrides_platform_env() {
+        let env = generate_env(
+            &app(vec![literal("POD_NAME", "custom")]),
+            "shop",
+            &Config::default(),
+        )
+        .unwrap();
+        let pod_names: Vec<&EnvVar> = env.iter().filter(|e| e.name == "POD_NAME").collect();
+        assert_eq!(pod_names.len(), 1);
+        assert_eq!(pod_names[0].value.as_deref(), Some("custom"));
+        assert!(env.iter().any(|e| e.name == POD_NAME_REFERENCE_ENV));
+    }
+
+    #[test]
+    fn explicit_env_can_not_override_reserved_env() {
+        let result = generate_env(
+            &app(vec![literal(POD_NAME_REFERENCE_ENV, "custom")]),
+            "shop",
+            &Config::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    fn rollout(pre_stop_sleep_seconds: i64, termination_grace_period_seconds: i64) -> Rollout {
+        Rollout {
+            pre_stop_sleep_seconds: Some(pre_stop_sleep_seconds),
+            termination_grace_period_seconds: Some(termination_grace_period_seconds),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn pre_stop_sleep_must_fit_in_grace_period() {
+        assert_eq!(pre_stop_sleep_seconds(&rollout(5, 30)).unwrap(), 5);
+        assert_eq!(pre_stop_sleep_seconds(&rollout(0, 0)).unwrap(), 0);
+        assert_eq!(
+            pre_stop_sleep_seconds(&Rollout::default()).unwrap(),
+            DEFAULT_PRE_STOP_SLEEP_SECONDS
+        );
+        assert!(pre_stop_sleep_seconds(&rollout(30, 30)).is_err());
+        assert!(pre_stop_sleep_seconds(&rollout(-1, 30)).is_err());
+        assert!(pre_stop_sleep_seconds(&rollout(0, -1)).is_err());
+    }
+
+    #[test]
+    fn immutable_changes_ignore_server_defaults() {
+        let app: Application = serde_json::from_value(serde_json::json!({
+            "apiVersion": "yakup.ibidem.no/v1",
+            "kind": "Application",
+            "metadata": {"name": "test-app"},
+            "spec": {
+                "image": "busybox:latest",
+                "filesFrom": [{"persistentVolume": {
+                    "name": "data", "mountPath": "/data", "size": "1Gi", "perReplica": true,
+                }}],
+            },
+        }))
+        .unwrap();
+        let existing = |size: &str| -> ExistingStatefulSet {
+            serde_json::from_value(serde_json::json!({
+                "serviceName": "test-app-headless",
+                "volumeClaimTemplates": [{
+                    "metadata": {"name": "data"},
+                    "spec": {
+                        "accessModes": ["ReadWriteOnce"],
+                        "resources": {"requests": {"storage": size}},
+                        "volumeMode": "Filesystem",
+                    },
+                    "status": {"phase": "Pending"},
+                }],
+            }))
+            .unwrap()
+        };
+        assert!(immutable_changes(&app, &existing("1Gi")).is_empty());
+        assert_eq!(
+            immutable_changes(&app, &existing("2Gi")),
+            vec!["volumeClaimTemplates"]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::DynamicObject;
+use kube::discovery::ApiResource;
+use kube::ResourceExt;
+use serde_json::{json, Value};
+use tracing::instrument;
+
+use api::application::v1::Application;
+use api::application::{HttpPort, PathType};
+use api::ingress_zone::v1::IngressZone;
+use api::ingress_zone::IngressZoneGateway;
+
+use crate::host_template::HostVariables;
+use crate::models::Operation;
+use crate::resource_creator::auth::gateway_auth_filters;
+use crate::resource_creator::ingress::{
+    backend_port, generate_annotations, generate_hosts, uses_gateway,
+};
+
+pub(crate) const INGRESS_ZONE_LABEL: &str = "yakup.ibidem.no/ingress_zone";
+
+pub fn http_route_resource() -> ApiResource {
+    ApiResource {
+        group: "gateway.networking.k8s.io".to_string(),
+        version: "v1".to_string(),
+        api_version: "gateway.networking.k8s.io/v1".to_string(),
+        kind: "HTTPRoute".to_string(),
+        plural: "httproutes".to_string(),
+    }
+}
+
+/// Generate HTTPRoutes for applications exposed in zones with a gateway.
+/// Stale routes are cleaned up in every zone, including zones that no longer use a gateway,
+/// but only when the Gateway API is installed, so other clusters never see HTTPRoute operations.
+#[instrument(skip(zones, app), fields(trace_id))]
+pub(crate) fn process(
+    app: &Arc<Application>,
+    zones: &HashMap<String, Arc<IngressZone>>,
+    denied_zones: &[String],
+    object_meta: ObjectMeta,
+    host_variables: &HostVariables,
+    gateway_api: bool,
+) -> Result<Vec<Operation>> {
+    let mut possible_routes: BTreeSet<String> = if gateway_api {
+        zones
+            .keys()
+            .map(|k| format!("{}-{}", app.name_any(), k))
+            .collect()
+    } else {
+        BTreeSet::new()
+    };
+
+    let mut routes: Vec<DynamicObject> = Vec::new();
+    if let Some(ports) = &app.spec.ports {
+        if let Some(http_port) = &ports.http {
+            routes.extend(generate_http_routes(
+                app,
+                zones,
+                denied_zones,
+                &object_meta,
+                http_port,
+                host_variables,
+            )?);
+        }
+    }
+    for route in &routes {
+        possible_routes.remove(&route.name_any());
+    }
+
+    let mut operations: Vec<Operation> = routes
+        .into_iter()
+        .map(|route| Operation::CreateOrUpdate(Arc::new(route)))
+        .collect();
+
+    for route_name in possible_routes {
+        let mut delete_route = DynamicObject::new(&route_name, &http_route_resource());
+        delete_route.metadata.namespace = object_meta.namespace.clone();
+        operations.push(Operation::DeleteIfExists(Arc::new(delete_route)));
+    }
+
+    Ok(operations)
+}
+
+fn generate_http_routes(
+    app: &Arc<Application>,
+    zones: &HashMap<String, Arc<IngressZone>>,
+    denied_zones: &[String],
+    object_meta: &ObjectMeta,
+    port: &HttpPort,
+    host_variables: &HostVariables,
+) -> Result<Vec<DynamicObject>> {
+    port.ingress
+        .iter()
+        .filter(|ingress| uses_gateway(zones, ingress))
+        .filter(|ingress| !denied_zones.contains(&ingress.zone))
+        .filter_map(|ingress| {
+            zones.get(&ingress.zone).map(|zone| {
+                generate_http_route(app, zone, object_meta.clone(), ingress, host_variables)
+            })
+        })
+        .collect()
+}
+
+fn generate_http_route(
+    app: &Arc<Application>,
+    zone: &IngressZone,
+    mut object_meta: ObjectMeta,
+    ingress: &api::application::Ingress,
+    host_variables: &HostVariables,
+) -> Result<DynamicObject> {
+    let gateway = zone
+        .spec
+        .gateway
+        .as_ref()
+        .ok_or_else(|| anyhow!("Ingress zone has no gateway"))?;
+
+    let hosts = generate_hosts(zone, ingress, host_variables)?;
+    let annotations = generate_annotations(zone, ingress)?;
+    let path_type = match ingress.path_type.clone().unwrap_or_default() {
+        PathType::Prefix => "PathPrefix",
+        PathType::Exact => "Exact",
+    };
+    let mut filters = gateway_auth_filters(zone, ingress)?;
+    if let Some(rewrite_prefix) = &ingress.rewrite_prefix {
+        if path_type != "PathPrefix" {
+            bail!("rewritePrefix requires pathType Prefix");
+        }
+        filters.push(json!({
+            "type": "URLRewrite",
+            "urlRewrite": {
+                "path": {"type": "ReplacePrefixMatch", "replacePrefixMatch": rewrite_prefix},
+            },
+        }));
+    }
+
+    // One rule per backend port, in the order the ports are first used
+    let mut rules: Vec<(i32, Vec<Value>)> = Vec::new();
+    for path in ingress.paths.iter() {
+        let (_, port) = backend_port(app, ingress, path)?;
+        let path_match = json!({"path": {"type": path_type, "value": path}});
+        match rules.iter_mut().find(|(rule_port, _)| *rule_port == port) {
+            Some((_, matches)) => matches.push(path_match),
+            None => rules.push((port, vec![path_match])),
+        }
+    }
+    let rules: Vec<Value> = rules
+        .into_iter()
+        .map(|(port, matches)| {
+            let mut rule = json!({
+                "matches": matches,
+                "backendRefs": [{"name": app.name_any(), "port": port}],
+            });
+            if !filters.is_empty() {
+                rule["filters"] = json!(filters);
+            }
+            rule
+        })
+        .collect();
+
+    let name = format!("{}-{}", app.name_any(), zone.name_any());
+    object_meta.name = Some(name.clone());
+    object_meta
+        .labels
+        .get_or_insert_with(BTreeMap::new)
+        .insert(INGRESS_ZONE_LABEL.to_string(), zone.name_any());
+    if !annotations.is_empty() {
+        object_meta
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(annotations);
+    }
+
+    let mut route = DynamicObject::new(&name, &http_route_resource()).data(json!({
+        "spec": {
+            "parentRefs": [parent_ref(gateway, gateway.section_name.as_ref())],
+            "hostnames": hosts,
+            "rules": rules,
+        },
+    }));
+    route.metadata = object_meta;
+    Ok(route)
+}
+
+pub(crate) fn parent_ref(gateway: &IngressZoneGateway, section_name: Option<&String>) -> Value {
+    let mut parent_ref = json!({"name": gateway.name});
+    if let Some(namespace) = &gateway.namespace {
+        parent_ref["namespace"] = json!(namespace);
+    }
+    if let Some(section_name) = section_name {
+        parent_ref["sectionName"] = json!(section_name);
+    }
+    parent_ref
+}
@@ -1,10 +1,11 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use api::application::v1::Application;
-use api::application::HttpPort;
+use api::application::{HttpPort, IngressOptions, PathType};
 use api::ingress_zone::v1::IngressZone;
+use api::ingress_zone::IngressController;
 use k8s_openapi::api::networking::v1::{
     HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule,
     IngressServiceBackend, IngressSpec, IngressTLS, ServiceBackendPort,
@@ -14,14 +15,21 @@ use kube::ResourceExt;
 use md5::{Digest, Md5};
 use tracing::instrument;
 
+use crate::host_template::{validate_host, HostTemplate, HostVariables};
 use crate::models::Operation;
+use crate::resource_creator::auth::auth_annotations;
+use crate::resource_creator::service::service_port_number;
 use crate::resource_creator::to_dynamic_object;
 
+const REGEX_PATH_TYPE: &str = "ImplementationSpecific";
+
 #[instrument(skip(zones, app), fields(trace_id))]
 pub(crate) fn process(
     app: &Arc<Application>,
     zones: &HashMap<String, Arc<IngressZone>>,
+    denied_zones: &[String],
     object_meta: ObjectMeta,
+    host_variables: &HostVariables,
 ) -> Result<Vec<Operation>> {
     let mut possible_ingresses: HashSet<String> =
         HashSet::from_iter(zones.keys().map(|k| format!("{}-{}", app.name_any(), k)));
@@ -32,9 +40,11 @@ pub(crate) fn process(
             ingresses.extend(generate_ingresses(
                 app.clone(),
                 zones,
+                denied_zones,
                 object_meta.clone(),
                 http_port,
-            ));
+                host_variables,
+            )?);
         }
     }
     // TODO: Test this
@@ -76,57 +86,307 @@ pub(crate) fn process(
     Ok(operations)
 }
 
+/// Applications in zones with a gateway get an HTTPRoute instead of an Ingress.
+pub(crate) fn uses_gateway(
+    zones: &HashMap<String, Arc<IngressZone>>,
+    ingress: &api::application::Ingress,
+) -> bool {
+    zones
+        .get(&ingress.zone)
+        .is_some_and(|zone| zone.spec.gateway.is_some())
+}
+
+/// Render the host of the application in the zone.
+/// An invalid host is an error for the whole application, rather than a broken Ingress or HTTPRoute.
+pub(crate) fn generate_host(zone: &IngressZone, host_variables: &HostVariables) -> Result<String> {
+    HostTemplate::parse(&zone.spec.host)
+        .and_then(|template| template.render(host_variables))
+        .map_err(|e| {
+            e.context(format!(
+                "generating host for ingress zone {}",
+                zone.name_any()
+            ))
+        })
+}
+
+/// All hosts of the application in the zone, starting with the generated host, followed by the
+/// extra hosts in sorted order.
+/// The TLS secret name is derived from this list, so reordering `hosts` must not change it.
+pub(crate) fn generate_hosts(
+    zone: &IngressZone,
+    ingress: &api::application::Ingress,
+    host_variables: &HostVariables,
+) -> Result<Vec<String>> {
+    let host = generate_host(zone, host_variables)?;
+    let mut extra_hosts = Vec::new();
+    for extra_host in ingress.hosts.iter() {
+        check_allowed_domain(zone, extra_host)?;
+        if *extra_host != host {
+            extra_hosts.push(extra_host.clone());
+        }
+    }
+    extra_hosts.sort();
+    extra_hosts.dedup();
+    let mut hosts = vec![host];
+    hosts.extend(extra_hosts);
+    Ok(hosts)
+}
+
+/// Hostnames requested by applications must be valid, and within the allowed domains of the zone.
+pub(crate) fn check_allowed_domain(zone: &IngressZone, host: &str) -> Result<()> {
+    validate_host(host)?;
+    if !zone
+        .spec
+        .allowed_domains
+        .iter()
+        .any(|domain| host == domain || host.ends_with(&format!(".{}", domain)))
+    {
+        bail!(
+            "host {} is not in the allowed domains of ingress zone {}",
+            host,
+            zone.name_any()
+        );
+    }
+    Ok(())
+}
+
+/// The name of the secret holding the certificate for the hosts.
+pub(crate) fn tls_secret_name(hosts: &[String]) -> String {
+    let hosts_md5 = Md5::digest(hosts.join(",").as_bytes());
+    let hosts_id = fast32::base32::CROCKFORD_LOWER.encode(&hosts_md5);
+    format!("cert-ingress-{}", hosts_id)
+}
+
+/// The name and number of the service port requests for the path are sent to.
+pub(crate) fn backend_port(
+    app: &Application,
+    ingress: &api::application::Ingress,
+    path: &str,
+) -> Result<(String, i32)> {
+    let name = ingress
+        .path_ports
+        .get(path)
+        .cloned()
+        .unwrap_or("http".to_string());
+    let number = service_port_number(app, &name)
+        .ok_or_else(|| anyhow!("path {} uses unknown service port {}", path, name))?;
+    Ok((name, number))
+}
+
+/// Annotations for the Ingress or HTTPRoute of the application in the zone.
+/// Zone annotations come first, then annotations generated from `options` and `auth`, then the
+/// annotations set by the application. Annotations from `options` and the application must be
+/// allowed by the zone.
+pub(crate) fn generate_annotations(
+    zone: &IngressZone,
+    ingress: &api::application::Ingress,
+) -> Result<BTreeMap<String, String>> {
+    let mut annotations = zone.spec.annotations.clone();
+    if let Some(options) = &ingress.options {
+        if zone.spec.gateway.is_some() {
+            bail!(
+                "ingress options are not supported in gateway zones, as used by ingress zone {}",
+                zone.name_any()
+            );
+        }
+        let option_annotations = match zone.spec.ingress_controller {
+            Some(IngressController::Nginx) => nginx_annotations(options),
+            None => bail!(
+                "ingress zone {} has no ingressController, ingress options are not supported",
+                zone.name_any()
+            ),
+        };
+        for key in option_annotations.keys() {
+            check_allowed_annotation(zone, key)?;
+        }
+        annotations.extend(option_annotations);
+    }
+    if zone.spec.gateway.is_none() {
+        annotations.extend(auth_annotations(zone, ingress)?);
+        annotations.extend(routing_annotations(zone, ingress)?);
+    }
+    for (key, value) in ingress.annotations.iter() {
+        check_allowed_annotation(zone, key)?;
+        annotations.insert(key.clone(), value.clone());
+    }
+    Ok(annotations)
+}
+
+/// Applications may only set annotations the zone allows, directly or through `options`.
+fn check_allowed_annotation(zone: &IngressZone, key: &str) -> Result<()> {
+    let allowed =
+        zone.spec
+            .allowed_annotations
+            .iter()
+            .any(|allowed| match allowed.strip_suffix('*') {
+                Some(prefix) => key.starts_with(prefix),
+                None => key == allowed,
+            });
+    if !allowed {
+        bail!(
+            "annotation {} is not allowed in ingress zone {}",
+            key,
+            zone.name_any()
+        );
+    }
+    Ok(())
+}
+
+/// Annotations for `rewritePrefix` and `httpsRedirect` in zones using an Ingress.
+fn routing_annotations(
+    zone: &IngressZone,
+    ingress: &api::application::Ingress,
+) -> Result<BTreeMap<String, String>> {
+    let mut annotations = BTreeMap::new();
+    if ingress.rewrite_prefix.is_none() && ingress.https_redirect.is_none() {
+        return Ok(annotations);
+    }
+    if zone.spec.ingress_controller != Some(IngressController::Nginx) {
+        bail!(
+            "ingress zone {} has no ingressController, rewrites and redirects are not supported",
+            zone.name_any()
+        );
+    }
+    const PREFIX: &str = "nginx.ingress.kubernetes.io";
+    if let Some(rewrite_prefix) = &ingress.rewrite_prefix {
+        if ingress.path_type.clone().unwrap_or_default() != PathType::Prefix {
+            bail!("rewritePrefix requires pathType Prefix");
+        }
+        if ingress
+            .options
+            .as_ref()
+            .is_some_and(|options| options.rewrite_target.is_some())
+        {
+            bail!("it is an error to set both rewritePrefix and options.rewriteTarget");
+        }
+        annotations.insert(format!("{}/use-regex", PREFIX), "true".to_string());
+        annotations.insert(
+            format!("{}/rewrite-target", PREFIX),
+            format!("{}/$2", rewrite_prefix.trim_end_matches('/')),
+        );
+    }
+    match ingress.https_redirect {
+        Some(true) => {
+            annotations.insert(format!("{}/force-ssl-redirect", PREFIX), "true".to_string());
+        }
+        Some(false) => {
+            annotations.insert(format!("{}/ssl-redirect", PREFIX), "false".to_string());
+        }
+        None => {}
+    }
+    Ok(annotations)
+}
+
+fn nginx_annotations(options: &IngressOptions) -> BTreeMap<String, String> {
+    const PREFIX: &str = "nginx.ingress.kubernetes.io";
+    let mut annotations = BTreeMap::new();
+    let mut add = |key: &str, value: String| {
+        annotations.insert(format!("{}/{}", PREFIX, key), value);
+    };
+    if let Some(max_body_size) = &options.max_body_size {
+        add("proxy-body-size", max_body_size.clone());
+    }
+    if let Some(timeout) = options.timeout_seconds {
+        add("proxy-read-timeout", timeout.to_string());
+        add("proxy-send-timeout", timeout.to_string());
+    }
+    if !options.allowed_source_ranges.is_empty() {
+        add(
+            "whitelist-source-range",
+            options.allowed_source_ranges.join(","),
+        );
+    }
+    if let Some(cors) = &options.cors {
+        add("enable-cors", "true".to_string());
+        if !cors.allowed_origins.is_empty() {
+            add("cors-allow-origin", cors.allowed_origins.join(", "));
+        }
+        if !cors.allowed_methods.is_empty() {
+            add("cors-allow-methods", cors.allowed_methods.join(", "));
+        }
+        if !cors.allowed_headers.is_empty() {
+            add("cors-allow-headers", cors.allowed_headers.join(", "));
+        }
+        if let Some(allow_credentials) = cors.allow_credentials {
+            add("cors-allow-credentials", allow_credentials.to_string());
+        }
+    }
+    if let Some(rewrite_target) = &options.rewrite_target {
+        add("rewrite-target", rewrite_target.clone());
+    }
+    annotations
+}
+
 fn generate_ingresses(
     app: Arc<Application>,
     zones: &HashMap<String, Arc<IngressZone>>,
+    denied_zones: &[String],
     object_meta: ObjectMeta,
     port: &HttpPort,
-) -> Vec<Ingress> {
-    let ingresses = port
-        .ingress
+    host_variables: &HostVariables,
+) -> Result<Vec<Ingress>> {
+    port.ingress
         .iter()
-        .map(|ingress| generate_ingress(app.clone(), zones, object_meta.clone(), ingress))
-        .filter_map(|ingress| match ingress {
-            Ok(ingress) => Some(ingress),
-            Err(e) => {
-                tracing::error!(error = e.to_string(), "Failed to create ingress");
+        .filter(|ingress| !uses_gateway(zones, ingress))
+        .filter(|ingress| !denied_zones.contains(&ingress.zone))
+        .filter_map(|ingress| match zones.get(&ingress.zone) {
+            Some(zone) => Some(generate_ingress(
+                app.clone(),
+                zone,
+                object_meta.clone(),
+                ingress,
+                host_variables,
+            )),
+            None => {
+                tracing::error!(zone = ingress.zone, "Ingress zone not found");
                 None
             }
         })
-        .collect();
-    ingresses
+        .collect()
 }
 
 fn generate_ingress(
     app: Arc<Application>,
-    zones: &HashMap<String, Arc<IngressZone>>,
+    zone: &IngressZone,
     mut object_meta: ObjectMeta,
     ingress: &api::application::Ingress,
+    host_variables: &HostVariables,
 ) -> Result<Ingress> {
-    let zone = zones
-        .get(&ingress.zone)
-        .ok_or_else(|| anyhow!("Ingress zone not found"))?;
+    let hosts = generate_hosts(zone, ingress, host_variables)?;
 
-    let host = zone.spec.host.replace("{appname}", app.name_any().as_str());
-
-    let paths = ingress
+    let paths: Vec<HTTPIngressPath> = ingress
         .paths
         .iter()
-        .map(|path| HTTPIngressPath {
-            backend: IngressBackend {
-                resource: None,
-                service: Some(IngressServiceBackend {
-                    name: app.name_any(),
-                    port: Some(ServiceBackendPort {
-                        name: Some("http".to_string()),
-                        number: None,
+        .map(|path| -> Result<HTTPIngressPath> {
+            let (port_name, _) = backend_port(&app, ingress, path)?;
+            // Rewrites use a regex path, where the second group is the rest of the path
+            let (path, path_type) = match &ingress.rewrite_prefix {
+                Some(_) if path == "/" => ("/()(.*)".to_string(), REGEX_PATH_TYPE.to_string()),
+                Some(_) => (
+                    format!("{}(/|$)(.*)", path.trim_end_matches('/')),
+                    REGEX_PATH_TYPE.to_string(),
+                ),
+                None => (
+                    path.clone(),
+                    ingress.path_type.clone().unwrap_or_default().to_string(),
+                ),
+            };
+            Ok(HTTPIngressPath {
+                backend: IngressBackend {
+                    resource: None,
+                    service: Some(IngressServiceBackend {
+                        name: app.name_any(),
+                        port: Some(ServiceBackendPort {
+                            name: Some(port_name),
+                            number: None,
+                        }),
                     }),
-                }),
-            },
-            path: Some(path.clone()),
-            path_type: ingress.path_type.clone().unwrap_or_default().to_string(),
+                },
+                path: Some(path),
+                path_type,
+            })
         })
-        .collect();
+        .collect::<Result<_>>()?;
 
     object_meta.name = Some(format!("{}-{}", app.name_any(), zone.name_any()));
     if let Some(labels) = &mut object_meta.labels {
@@ -141,30 +401,43 @@ fn generate_ingress(
         )]));
     }
 
+    let mut annotations = BTreeMap::new();
     let tls = match &zone.spec.tls {
         Some(zone_tls) => {
-            object_meta.annotations = Some(BTreeMap::from([(
+            annotations.insert(
                 "cert-manager.io/cluster-issuer".to_string(),
                 zone_tls.cluster_issuer.clone().unwrap_or_default(),
-            )]));
-            let hosts_md5 = Md5::digest(host.clone().as_bytes());
-            let hosts_id = fast32::base32::CROCKFORD_LOWER.encode(&hosts_md5);
+            );
             Some(vec![IngressTLS {
-                hosts: Some(vec![host.clone()]),
-                secret_name: Some(format!("cert-ingress-{}", hosts_id)),
+                hosts: Some(hosts.clone()),
+                secret_name: Some(tls_secret_name(&hosts)),
             }])
         }
         None => None,
     };
+    annotations.extend(generate_annotations(zone, ingress)?);
+    if !annotations.is_empty() {
+        object_meta
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(annotations);
+    }
 
     let ingress = Ingress {
         metadata: object_meta,
         spec: Some(IngressSpec {
             ingress_class_name: zone.spec.ingress_class.clone(),
-            rules: Some(vec![IngressRule {
-                host: Some(host.clone()),
-                http: Some(HTTPIngressRuleValue { paths }),
-            }]),
+            rules: Some(
+                hosts
+                    .iter()
+                    .map(|host| IngressRule {
+                        host: Some(host.clone()),
+                        http: Some(HTTPIngressRuleValue {
+                            paths: paths.clone(),
+                        }),
+                    })
+                    .collect(),
+            ),
             tls,
             ..Default::default()
         }),
@@ -2,23 +2,100 @@ use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
+use k8s_openapi::api::core::v1::PersistentVolumeClaim;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{ObjectMeta, OwnerReference};
-use k8s_openapi::serde::Serialize;
+use k8s_openapi::serde::{Deserialize, Serialize};
 use kube::api::DynamicObject;
 use kube::api::Resource;
 use kube::discovery::ApiResource;
 use kube::ResourceExt;
 use tracing::instrument;
 
+use crate::config::Config;
+use crate::host_template::HostVariables;
 use crate::models::Operation;
+use crate::zone_access::denied_zones;
 use api::application::v1::Application;
 use api::ingress_zone::v1::IngressZone;
 
+mod auth;
+mod config_map;
 mod deployment;
+mod http_route;
 mod ingress;
+mod network_policy;
+mod persistent_volume_claim;
+mod pod_disruption_budget;
+mod rbac;
+mod redirect;
 mod service;
 mod service_account;
 
+pub use http_route::http_route_resource;
+
+/// State gathered from the cluster that the generated resources depend on.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterState {
+    /// Hash of the contents of the configmaps and secrets the application should roll out on changes to.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+
+    /// Configmaps generated for earlier versions of `files`, that are no longer in use.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stale_config_maps: Vec<String>,
+
+    /// Labels on the application namespace.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub namespace_labels: BTreeMap<String, String>,
+
+    /// The image pinned to a digest, deployed instead of `spec.image`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolved_image: Option<String>,
+
+    /// The Deployment and StatefulSet of the application that exist, and whether they are ready.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub workloads: BTreeMap<String, bool>,
+
+    /// The clusterIP of the existing Service of the application, `None` for a headless Service.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_cluster_ip: Option<String>,
+
+    /// Whether the Gateway API is installed, so HTTPRoutes can exist.
+    #[serde(default)]
+    pub gateway_api: bool,
+
+    /// Hosts of the application already used by other applications, by zone.
+    /// The application is not exposed in these zones until the conflict is resolved.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub host_conflicts: BTreeMap<String, Vec<String>>,
+
+    /// The fields of the existing StatefulSet of the application that can't be changed.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub existing_stateful_set: Option<ExistingStatefulSet>,
+}
+
+/// The fields of a StatefulSet that can't be changed after it is created.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExistingStatefulSet {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_name: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub volume_claim_templates: Vec<PersistentVolumeClaim>,
+}
+
 trait Owner {
     fn owner_reference(&self) -> OwnerReference;
 }
@@ -36,10 +113,12 @@ impl Owner for Application {
     }
 }
 
-#[instrument(skip(zones, app), fields(trace_id))]
+#[instrument(skip(zones, app, config, cluster_state), fields(trace_id))]
 pub fn process(
     app: Arc<Application>,
     zones: &HashMap<String, Arc<IngressZone>>,
+    config: &Config,
+    cluster_state: &ClusterState,
 ) -> Result<Vec<Operation>> {
     let app_name = app.name_any();
     let namespace = app.namespace().unwrap_or("default".to_string());
@@ -50,26 +129,164 @@ pub fn process(
             "yakup".to_string(),
         ),
     ]);
+    let mut object_labels = pass_through(app.labels(), &config.pass_through.labels);
+    object_labels.extend(labels.clone());
+    let annotations = pass_through(app.annotations(), &config.pass_through.annotations);
     let object_meta = ObjectMeta {
         name: Some(app_name.clone()),
         namespace: Some(namespace.clone()),
-        labels: Some(labels.clone()),
+        labels: Some(object_labels),
+        annotations: (!annotations.is_empty()).then_some(annotations),
         owner_references: Some(vec![app.owner_reference()]),
         ..Default::default()
     };
 
     let mut operations = Vec::new();
+    operations.extend(config_map::process(
+        &app,
+        object_meta.clone(),
+        cluster_state,
+    )?);
     operations.extend(deployment::process(
         &app,
         object_meta.clone(),
         labels.clone(),
+        config,
+        cluster_state,
+    )?);
+    operations.extend(service::process(
+        &app,
+        object_meta.clone(),
+        labels.clone(),
+        cluster_state,
+    )?);
+    operations.extend(service_account::process(&app, object_meta.clone())?);
+    let host_variables = HostVariables::new(&app, config, cluster_state);
+    let mut denied_zones = denied_zones(&app, zones, cluster_state);
+    denied_zones.extend(cluster_state.host_conflicts.keys().cloned());
+    denied_zones.sort();
+    denied_zones.dedup();
+    operations.extend(ingress::process(
+        &app,
+        zones,
+        &denied_zones,
+        object_meta.clone(),
+        &host_variables,
+    )?);
+    operations.extend(http_route::process(
+        &app,
+        zones,
+        &denied_zones,
+        object_meta.clone(),
+        &host_variables,
+        cluster_state.gateway_api,
+    )?);
+    operations.extend(persistent_volume_claim::process(&app, object_meta.clone())?);
+    operations.extend(pod_disruption_budget::process(
+        &app,
+        object_meta.clone(),
+        labels.clone(),
+    )?);
+    operations.extend(network_policy::process(
+        &app,
+        zones,
+        &denied_zones,
+        object_meta.clone(),
+        labels.clone(),
     )?);
-    operations.extend(service::process(&app, object_meta.clone(), labels.clone())?);
-    operations.extend(service_account::process(object_meta.clone())?);
-    operations.extend(ingress::process(&app, zones, object_meta.clone())?);
+    operations.extend(redirect::process(
+        &app,
+        zones,
+        &denied_zones,
+        object_meta.clone(),
+        &host_variables,
+        cluster_state.gateway_api,
+    )?);
+    operations.extend(rbac::process(&app, object_meta.clone(), config)?);
     Ok(operations)
 }
 
+/// The hosts handed out to the application by an ingress in the zone.
+pub fn zone_hosts(
+    app: &Application,
+    zone: &IngressZone,
+    ingress: &api::application::Ingress,
+    config: &Config,
+    cluster_state: &ClusterState,
+) -> Result<Vec<String>> {
+    let host_variables = HostVariables::new(app, config, cluster_state);
+    ingress::generate_hosts(zone, ingress, &host_variables)
+}
+
+/// The labels or annotations with keys starting with one of the prefixes.
+fn pass_through(from: &BTreeMap<String, String>, prefixes: &[String]) -> BTreeMap<String, String> {
+    from.iter()
+        .filter(|(key, _)| {
+            prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_str()))
+        })
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect()
+}
+
+/// The effective number of replicas of the application.
+fn replicas(app: &Application) -> i32 {
+    // Default to 2 replicas for HTTP applications, 1 for others
+    let mut replicas = 1;
+    if let Some(ports) = &app.spec.ports {
+        if let Some(http_port) = &ports.http {
+            if !http_port.ingress.is_empty() {
+                replicas = 2 // Default to 2 replicas for HTTP applications with ingress
+            }
+        }
+    };
+    replicas
+}
+
+/// Applications with per-replica volumes are deployed as a StatefulSet instead of a Deployment.
+pub fn is_stateful(app: &Application) -> bool {
+    app.spec
+        .files_from
+        .iter()
+        .filter_map(|ff| ff.persistent_volume.as_ref())
+        .any(|pv| pv.per_replica.unwrap_or(false))
+}
+
+/// The kind of workload the application runs as, and the kind it would be switching from.
+fn workload_kinds(app: &Application) -> (&'static str, &'static str) {
+    if is_stateful(app) {
+        ("StatefulSet", "Deployment")
+    } else {
+        ("Deployment", "StatefulSet")
+    }
+}
+
+/// When switching between Deployment and StatefulSet, the old workload is kept until the new one is ready.
+pub fn keep_replaced_workload(app: &Application, cluster_state: &ClusterState) -> bool {
+    let (kind, replaced_kind) = workload_kinds(app);
+    cluster_state.workloads.contains_key(replaced_kind)
+        && cluster_state.workloads.get(kind) != Some(&true)
+}
+
+/// The fields the application changes on its existing StatefulSet, that can't be changed.
+/// The StatefulSet keeps the existing values until it is deleted, which can be done with
+/// `--cascade=orphan` to keep the pods running while it is recreated.
+pub fn immutable_stateful_set_changes(
+    app: &Application,
+    cluster_state: &ClusterState,
+) -> Vec<&'static str> {
+    match &cluster_state.existing_stateful_set {
+        Some(existing) if is_stateful(app) => deployment::immutable_changes(app, existing),
+        _ => Vec::new(),
+    }
+}
+
+/// StatefulSets are governed by a headless service, giving each pod a stable DNS name.
+fn headless_service_name(app: &Application) -> String {
+    format!("{}-headless", app.name_any())
+}
+
 fn to_dynamic_object<K: Resource + ResourceExt + Serialize>(resource: K) -> Result<DynamicObject>
 where
     K::DynamicType: Default,
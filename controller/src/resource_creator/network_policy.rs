@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use k8s_openapi::api::networking::v1::{
+    IPBlock, NetworkPolicy, NetworkPolicyEgressRule, NetworkPolicyIngressRule, NetworkPolicyPeer,
+    NetworkPolicyPort, NetworkPolicySpec,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use tracing::instrument;
+
+use api::application::v1::Application;
+use api::application::AccessRule;
+use api::ingress_zone::v1::IngressZone;
+
+use crate::models::Operation;
+use crate::resource_creator::to_dynamic_object;
+
+const APP_NAME_LABEL: &str = "app.kubernetes.io/name";
+const NAMESPACE_NAME_LABEL: &str = "kubernetes.io/metadata.name";
+
+#[instrument(skip(app, zones, denied_zones, object_meta), fields(trace_id))]
+pub(crate) fn process(
+    app: &Arc<Application>,
+    zones: &HashMap<String, Arc<IngressZone>>,
+    denied_zones: &[String],
+    object_meta: ObjectMeta,
+    labels: BTreeMap<String, String>,
+) -> Result<Vec<Operation>> {
+    let access_policy = match &app.spec.access_policy {
+        Some(access_policy) => access_policy,
+        None => {
+            return Ok(vec![Operation::DeleteIfExists(Arc::new(
+                to_dynamic_object(NetworkPolicy {
+                    metadata: object_meta,
+                    ..Default::default()
+                })?,
+            ))]);
+        }
+    };
+
+    let mut ingress = access_policy
+        .inbound
+        .iter()
+        .map(|rule| -> Result<NetworkPolicyIngressRule> {
+            Ok(NetworkPolicyIngressRule {
+                from: Some(vec![generate_peer(rule)?]),
+                ports: None,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ingress.extend(generate_ingress_controller_rules(app, zones, denied_zones)?);
+
+    let mut policy_types = vec!["Ingress".to_string()];
+    let egress = if access_policy.outbound.is_empty() {
+        None
+    } else {
+        policy_types.push("Egress".to_string());
+        let mut egress = access_policy
+            .outbound
+            .iter()
+            .map(|rule| -> Result<NetworkPolicyEgressRule> {
+                Ok(NetworkPolicyEgressRule {
+                    to: Some(vec![generate_peer(rule)?]),
+                    ports: None,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        egress.push(generate_dns_rule());
+        Some(egress)
+    };
+
+    let network_policy = NetworkPolicy {
+        metadata: object_meta,
+        spec: Some(NetworkPolicySpec {
+            pod_selector: Some(LabelSelector {
+                match_labels: Some(labels),
+                ..Default::default()
+            }),
+            ingress: Some(ingress),
+            egress,
+            policy_types: Some(policy_types),
+        }),
+    };
+
+    Ok(vec![Operation::CreateOrUpdate(Arc::new(
+        to_dynamic_object(network_policy)?,
+    ))])
+}
+
+fn generate_peer(rule: &AccessRule) -> Result<NetworkPolicyPeer> {
+    if let Some(cidr) = &rule.cidr {
+        if rule.application.is_some() || rule.namespace.is_some() {
+            bail!("an access rule with a cidr can not also have an application or namespace");
+        }
+        return Ok(NetworkPolicyPeer {
+            ip_block: Some(IPBlock {
+                cidr: cidr.clone(),
+                except: if rule.except.is_empty() {
+                    None
+                } else {
+                    Some(rule.except.clone())
+                },
+            }),
+            ..Default::default()
+        });
+    }
+    if rule.application.is_none() && rule.namespace.is_none() {
+        bail!("an access rule must have an application, a namespace or a cidr");
+    }
+    Ok(NetworkPolicyPeer {
+        ip_block: None,
+        namespace_selector: rule
+            .namespace
+            .as_ref()
+            .map(|namespace| match_label(NAMESPACE_NAME_LABEL, namespace)),
+        // Without an application, select all pods in the namespace
+        pod_selector: Some(
+            rule.application
+                .as_ref()
+                .map(|application| match_label(APP_NAME_LABEL, application))
+                .unwrap_or_default(),
+        ),
+    })
+}
+
+/// Allow the ingress controllers of the zones the application is exposed in to reach the http port.
+/// Zones the namespace of the application is not allowed to use are left out.
+fn generate_ingress_controller_rules(
+    app: &Arc<Application>,
+    zones: &HashMap<String, Arc<IngressZone>>,
+    denied_zones: &[String],
+) -> Result<Option<NetworkPolicyIngressRule>> {
+    let Some(http_port) = app
+        .spec
+        .ports
+        .as_ref()
+        .and_then(|ports| ports.http.as_ref())
+    else {
+        return Ok(None);
+    };
+    let mut namespaces = BTreeSet::new();
+    for ingress in http_port.ingress.iter() {
+        if denied_zones.contains(&ingress.zone) {
+            continue;
+        }
+        let Some(zone) = zones.get(&ingress.zone) else {
+            continue;
+        };
+        // Without the namespace, the application would be unreachable from the zone
+        match &zone.spec.controller_namespace {
+            Some(namespace) => namespaces.insert(namespace.clone()),
+            None => bail!(
+                "ingress zone {} has no controllerNamespace, and can not be used with accessPolicy",
+                ingress.zone
+            ),
+        };
+    }
+    if namespaces.is_empty() {
+        return Ok(None);
+    }
+    // Named container ports, matching the service ports used by `pathPorts`
+    let port_names: BTreeSet<String> = std::iter::once("http".to_string())
+        .chain(
+            http_port
+                .ingress
+                .iter()
+                .flat_map(|ingress| ingress.path_ports.values().cloned()),
+        )
+        .collect();
+    Ok(Some(NetworkPolicyIngressRule {
+        from: Some(
+            namespaces
+                .iter()
+                .map(|namespace| NetworkPolicyPeer {
+                    namespace_selector: Some(match_label(NAMESPACE_NAME_LABEL, namespace)),
+                    ..Default::default()
+                })
+                .collect(),
+        ),
+        ports: Some(
+            port_names
+                .into_iter()
+                .map(|name| NetworkPolicyPort {
+                    port: Some(IntOrString::String(name)),
+                    ..Default::default()
+                })
+                .collect(),
+        ),
+    }))
+}
+
+fn generate_dns_rule() -> NetworkPolicyEgressRule {
+    NetworkPolicyEgressRule {
+        to: Some(vec![NetworkPolicyPeer {
+            namespace_selector: Some(match_label(NAMESPACE_NAME_LABEL, "kube-system")),
+            pod_selector: Some(match_label("k8s-app", "kube-dns")),
+            ..Default::default()
+        }]),
+        ports: Some(
+            ["UDP", "TCP"]
+                .iter()
+                .map(|protocol| NetworkPolicyPort {
+                    port: Some(IntOrString::Int(53)),
+                    protocol: Some(protocol.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+        ),
+    }
+}
+
+fn match_label(key: &str, value: &str) -> LabelSelector {
+    LabelSelector {
+        match_labels: Some(BTreeMap::from([(key.to_string(), value.to_string())])),
+        ..Default::default()
+    }
+}
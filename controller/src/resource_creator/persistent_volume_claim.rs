@@ -0,0 +1,181 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use k8s_openapi::api::core::v1::{
+    PersistentVolumeClaim, PersistentVolumeClaimSpec, VolumeResourceRequirements,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::ResourceExt;
+use tracing::instrument;
+
+use api::application::v1::Application;
+use api::application::{AccessMode, FilesFromPersistentVolume, RolloutStrategy};
+
+use crate::models::Operation;
+use crate::resource_creator::{is_stateful, replicas, to_dynamic_object};
+
+/// Shared persistent volumes get a PersistentVolumeClaim.
+/// Claims are never deleted by yakup, to avoid losing data when a volume is removed from the spec,
+/// and have no owner reference, to survive deletion of the application.
+/// The spec of a claim is mostly immutable once bound, so existing claims are left as they are.
+#[instrument(skip(app, object_meta), fields(trace_id))]
+pub(crate) fn process(app: &Arc<Application>, object_meta: ObjectMeta) -> Result<Vec<Operation>> {
+    app.spec
+        .files_from
+        .iter()
+        .filter_map(|ff| ff.persistent_volume.as_ref())
+        .filter(|pv| !pv.per_replica.unwrap_or(false))
+        .map(|pv| -> Result<Operation> {
+            check_single_writer(app, pv)?;
+            let mut metadata = object_meta.clone();
+            metadata.name = Some(persistent_volume_claim_name(app, pv));
+            metadata.owner_references = None;
+            let pvc = PersistentVolumeClaim {
+                metadata,
+                spec: Some(generate_persistent_volume_claim_spec(pv)),
+                ..Default::default()
+            };
+            Ok(Operation::CreateIfMissing(Arc::new(to_dynamic_object(
+                pvc,
+            )?)))
+        })
+        .collect()
+}
+
+/// A shared volume that can only be mounted by one node or pod at a time needs a single replica.
+/// Deployments must also use the Recreate strategy, so the old pod is stopped before the new starts.
+/// StatefulSets always stop the old pod first.
+fn check_single_writer(app: &Application, pv: &FilesFromPersistentVolume) -> Result<()> {
+    let access_mode = pv.access_mode.clone().unwrap_or_default();
+    if !matches!(
+        access_mode,
+        AccessMode::ReadWriteOnce | AccessMode::ReadWriteOncePod
+    ) {
+        return Ok(());
+    }
+    if replicas(app) > 1 {
+        bail!(
+            "persistentVolume {} with accessMode {} can not be shared by more than one replica",
+            pv.name,
+            access_mode
+        );
+    }
+    let strategy = app
+        .spec
+        .rollout
+        .as_ref()
+        .and_then(|rollout| rollout.strategy.clone())
+        .unwrap_or_default();
+    if !is_stateful(app) && strategy != RolloutStrategy::Recreate {
+        bail!(
+            "persistentVolume {} with accessMode {} requires the Recreate rollout strategy",
+            pv.name,
+            access_mode
+        );
+    }
+    Ok(())
+}
+
+pub(crate) fn persistent_volume_claim_name(
+    app: &Application,
+    pv: &FilesFromPersistentVolume,
+) -> String {
+    format!("{}-{}", app.name_any(), pv.name)
+}
+
+pub(crate) fn generate_persistent_volume_claim_spec(
+    pv: &FilesFromPersistentVolume,
+) -> PersistentVolumeClaimSpec {
+    PersistentVolumeClaimSpec {
+        access_modes: Some(vec![pv.access_mode.clone().unwrap_or_default().to_string()]),
+        resources: Some(VolumeResourceRequirements {
+            requests: Some(BTreeMap::from([("storage".to_string(), pv.size.clone())])),
+            ..Default::default()
+        }),
+        storage_class_name: pv.storage_class.clone(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api::application::v1::ApplicationSpec;
+    use api::application::{FilesFrom, HttpPort, Ingress, Ports, Rollout};
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+
+    use super::*;
+
+    fn app(access_mode: AccessMode, strategy: RolloutStrategy, ingress: bool) -> Application {
+        let pv = FilesFromPersistentVolume {
+            name: "data".to_string(),
+            size: Quantity("1Gi".to_string()),
+            access_mode: Some(access_mode),
+            ..Default::default()
+        };
+        let http = HttpPort {
+            port: 8080,
+            ingress: if ingress {
+                vec![Ingress {
+                    zone: "public".to_string(),
+                    ..Default::default()
+                }]
+            } else {
+                vec![]
+            },
+            ..Default::default()
+        };
+        Application::new(
+            "test-app",
+            ApplicationSpec {
+                image: "busybox:latest".to_string(),
+                files_from: vec![FilesFrom {
+                    persistent_volume: Some(pv),
+                    ..Default::default()
+                }],
+                ports: Some(Ports {
+                    http: Some(http),
+                    ..Default::default()
+                }),
+                rollout: Some(Rollout {
+                    strategy: Some(strategy),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn check(app: &Application) -> Result<()> {
+        let pv = app.spec.files_from[0].persistent_volume.as_ref().unwrap();
+        check_single_writer(app, pv)
+    }
+
+    #[test]
+    fn read_write_once_requires_single_replica_and_recreate() {
+        assert!(check(&app(
+            AccessMode::ReadWriteOnce,
+            RolloutStrategy::Recreate,
+            false
+        ))
+        .is_ok());
+        assert!(check(&app(
+            AccessMode::ReadWriteOnce,
+            RolloutStrategy::Recreate,
+            true
+        ))
+        .is_err());
+        assert!(check(&app(
+            AccessMode::ReadWriteOncePod,
+            RolloutStrategy::RollingUpdate,
+            false
+        ))
+        .is_err());
+        assert!(check(&app(
+            AccessMode::ReadWriteMany,
+            RolloutStrategy::RollingUpdate,
+            true
+        ))
+        .is_ok());
+    }
+}
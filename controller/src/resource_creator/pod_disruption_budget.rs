@@ -0,0 +1,59 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use k8s_openapi::api::policy::v1::{PodDisruptionBudget, PodDisruptionBudgetSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, ObjectMeta};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use tracing::instrument;
+
+use api::application::v1::Application;
+
+use crate::models::Operation;
+use crate::resource_creator::{replicas, to_dynamic_object};
+
+#[instrument(skip(app, object_meta), fields(trace_id))]
+pub(crate) fn process(
+    app: &Arc<Application>,
+    object_meta: ObjectMeta,
+    labels: BTreeMap<String, String>,
+) -> Result<Vec<Operation>> {
+    let overrides = app.spec.pod_disruption_budget.clone().unwrap_or_default();
+    let enabled = overrides.enabled.unwrap_or(replicas(app) > 1);
+    if !enabled {
+        return Ok(vec![Operation::DeleteIfExists(Arc::new(
+            to_dynamic_object(PodDisruptionBudget {
+                metadata: object_meta,
+                ..Default::default()
+            })?,
+        ))]);
+    }
+
+    let (max_unavailable, min_available) =
+        match (overrides.max_unavailable, overrides.min_available) {
+            (Some(_), Some(_)) => {
+                bail!(
+                    "only one of maxUnavailable and minAvailable can be set in podDisruptionBudget"
+                )
+            }
+            (None, None) => (Some(IntOrString::Int(1)), None),
+            (max_unavailable, min_available) => (max_unavailable, min_available),
+        };
+    let pdb = PodDisruptionBudget {
+        metadata: object_meta,
+        spec: Some(PodDisruptionBudgetSpec {
+            max_unavailable,
+            min_available,
+            selector: Some(LabelSelector {
+                match_labels: Some(labels),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    Ok(vec![Operation::CreateOrUpdate(Arc::new(
+        to_dynamic_object(pdb)?,
+    ))])
+}
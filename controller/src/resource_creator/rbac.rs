@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use k8s_openapi::api::rbac::v1::{PolicyRule, Role, RoleBinding, RoleRef, Subject};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use tracing::instrument;
+
+use api::application::v1::Application;
+use api::application::KubernetesAccess;
+
+use crate::config::{Config, KubernetesAccessPolicy};
+use crate::models::Operation;
+use crate::resource_creator::service_account::service_account_name;
+use crate::resource_creator::to_dynamic_object;
+
+/// Grant the ServiceAccount of the application the requested access in its own namespace.
+#[instrument(skip(app, object_meta, config), fields(trace_id))]
+pub(crate) fn process(
+    app: &Arc<Application>,
+    object_meta: ObjectMeta,
+    config: &Config,
+) -> Result<Vec<Operation>> {
+    if app.spec.kubernetes_access.is_empty() {
+        return Ok(vec![
+            Operation::DeleteIfExists(Arc::new(to_dynamic_object(Role {
+                metadata: object_meta.clone(),
+                ..Default::default()
+            })?)),
+            Operation::DeleteIfExists(Arc::new(to_dynamic_object(RoleBinding {
+                metadata: object_meta,
+                ..Default::default()
+            })?)),
+        ]);
+    }
+
+    let mut rules = Vec::new();
+    for access in app.spec.kubernetes_access.iter() {
+        check_allowed(access, &config.kubernetes_access)?;
+        rules.push(PolicyRule {
+            api_groups: Some(api_groups(access)),
+            resources: Some(access.resources.clone()),
+            resource_names: (!access.resource_names.is_empty())
+                .then(|| access.resource_names.clone()),
+            verbs: access.verbs.clone(),
+            ..Default::default()
+        });
+    }
+    let role = Role {
+        metadata: object_meta.clone(),
+        rules: Some(rules),
+    };
+    let role_binding = RoleBinding {
+        metadata: object_meta.clone(),
+        role_ref: RoleRef {
+            api_group: "rbac.authorization.k8s.io".to_string(),
+            kind: "Role".to_string(),
+            name: object_meta.name.clone().unwrap_or_default(),
+        },
+        subjects: Some(vec![Subject {
+            kind: "ServiceAccount".to_string(),
+            name: service_account_name(app),
+            namespace: object_meta.namespace.clone(),
+            ..Default::default()
+        }]),
+    };
+
+    Ok(vec![
+        Operation::CreateOrUpdate(Arc::new(to_dynamic_object(role)?)),
+        Operation::CreateOrUpdate(Arc::new(to_dynamic_object(role_binding)?)),
+    ])
+}
+
+fn api_groups(access: &KubernetesAccess) -> Vec<String> {
+    if access.api_groups.is_empty() {
+        vec!["".to_string()]
+    } else {
+        access.api_groups.clone()
+    }
+}
+
+/// An allowed `*` covers every resource in the API group, but not subresources or a requested `*`,
+/// as that would grant subresources such as `pods/exec` too.
+fn resource_matches(allowed: &str, resource: &str) -> bool {
+    if allowed == "*" {
+        resource != "*" && !resource.contains('/')
+    } else {
+        allowed == resource
+    }
+}
+
+/// Reject access that isn't covered by the controller allowlist.
+fn check_allowed(access: &KubernetesAccess, policy: &KubernetesAccessPolicy) -> Result<()> {
+    if access.resources.is_empty() || access.verbs.is_empty() {
+        bail!("kubernetesAccess entries must list both resources and verbs");
+    }
+    for api_group in api_groups(access).iter() {
+        for resource in access.resources.iter() {
+            for verb in access.verbs.iter() {
+                let allowed = policy.allowed.iter().any(|allowed| {
+                    allowed.api_group == *api_group
+                        && allowed
+                            .resources
+                            .iter()
+                            .any(|r| resource_matches(r, resource))
+                        && allowed.verbs.iter().any(|v| v == "*" || v == verb)
+                });
+                if !allowed {
+                    let group = if api_group.is_empty() {
+                        "core"
+                    } else {
+                        api_group
+                    };
+                    bail!(
+                        "kubernetesAccess to {} {} in API group {} is not allowed",
+                        verb,
+                        resource,
+                        group
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::AllowedAccess;
+
+    use super::*;
+
+    fn access(api_groups: &[&str], resources: &[&str], verbs: &[&str]) -> KubernetesAccess {
+        KubernetesAccess {
+            api_groups: api_groups.iter().map(|g| g.to_string()).collect(),
+            resources: resources.iter().map(|r| r.to_string()).collect(),
+            verbs: verbs.iter().map(|v| v.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_policy_allows_reading_pods() {
+        let policy = KubernetesAccessPolicy::default();
+        assert!(check_allowed(&access(&[], &["pods"], &["get", "list"]), &policy).is_ok());
+        assert!(check_allowed(
+            &access(&["coordination.k8s.io"], &["leases"], &["update"]),
+            &policy
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn default_policy_rejects_sensitive_access() {
+        let policy = KubernetesAccessPolicy::default();
+        for rejected in [
+            access(&[], &["secrets"], &["get"]),
+            access(&[], &["pods/exec"], &["create"]),
+            access(&[], &["pods"], &["*"]),
+            access(&[], &["*"], &["get"]),
+            access(&["*"], &["pods"], &["get"]),
+            access(&[], &["pods"], &[]),
+        ] {
+            assert!(
+                check_allowed(&rejected, &policy).is_err(),
+                "{rejected:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn wildcard_resource_excludes_subresources() {
+        let policy = KubernetesAccessPolicy {
+            allowed: vec![AllowedAccess {
+                api_group: "".to_string(),
+                resources: vec!["*".to_string()],
+                verbs: vec!["get".to_string()],
+            }],
+        };
+        assert!(check_allowed(&access(&[], &["pods"], &["get"]), &policy).is_ok());
+        assert!(check_allowed(&access(&[], &["pods/log"], &["get"]), &policy).is_err());
+        assert!(check_allowed(&access(&[], &["*"], &["get"]), &policy).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use k8s_openapi::api::networking::v1::{
+    HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule,
+    IngressServiceBackend, IngressSpec, IngressTLS, ServiceBackendPort,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::api::DynamicObject;
+use kube::ResourceExt;
+use serde_json::json;
+use tracing::instrument;
+
+use api::application::v1::Application;
+use api::ingress_zone::v1::IngressZone;
+use api::ingress_zone::IngressController;
+
+use crate::host_template::HostVariables;
+use crate::models::Operation;
+use crate::resource_creator::http_route::{http_route_resource, parent_ref, INGRESS_ZONE_LABEL};
+use crate::resource_creator::ingress::{check_allowed_domain, generate_host, tls_secret_name};
+use crate::resource_creator::to_dynamic_object;
+
+/// Generate redirects from old hostnames to the host of the application in each zone, and
+/// HTTP to HTTPS redirects in gateway zones.
+/// Zones using an Ingress get a separate Ingress for the old hostnames, gateway zones get separate
+/// HTTPRoutes, as a redirect applies to every request the object matches.
+/// Stale redirects are cleaned up in every zone, so nothing is left behind when a zone switches
+/// between an Ingress and a gateway.
+#[instrument(skip(zones, app), fields(trace_id))]
+pub(crate) fn process(
+    app: &Arc<Application>,
+    zones: &HashMap<String, Arc<IngressZone>>,
+    denied_zones: &[String],
+    object_meta: ObjectMeta,
+    host_variables: &HostVariables,
+    gateway_api: bool,
+) -> Result<Vec<Operation>> {
+    let mut possible_ingresses: BTreeSet<String> = BTreeSet::new();
+    let mut possible_routes: BTreeSet<String> = BTreeSet::new();
+    for name in zones.keys() {
+        possible_ingresses.insert(redirect_name(app, name));
+        if gateway_api {
+            possible_routes.insert(redirect_name(app, name));
+            possible_routes.insert(https_redirect_name(app, name));
+        }
+    }
+
+    let mut operations = Vec::new();
+    let ingresses = app
+        .spec
+        .ports
+        .iter()
+        .filter_map(|ports| ports.http.as_ref())
+        .flat_map(|http| http.ingress.iter())
+        .filter(|ingress| !denied_zones.contains(&ingress.zone));
+    for ingress in ingresses {
+        let Some(zone) = zones.get(&ingress.zone) else {
+            continue;
+        };
+        let target = generate_host(zone, host_variables)?;
+        for host in ingress.redirect_from.iter() {
+            check_allowed_domain(zone, host)?;
+        }
+        let mut objects = Vec::new();
+        if zone.spec.gateway.is_some() {
+            if !ingress.redirect_from.is_empty() {
+                objects.push(generate_redirect_route(
+                    app,
+                    zone,
+                    object_meta.clone(),
+                    &ingress.redirect_from,
+                    &target,
+                )?);
+            }
+            if ingress.https_redirect == Some(false) {
+                bail!(
+                    "httpsRedirect: false is not supported in gateway zone {}",
+                    ingress.zone
+                );
+            }
+            if ingress.https_redirect == Some(true) {
+                objects.push(generate_https_redirect_route(
+                    app,
+                    zone,
+                    object_meta.clone(),
+                    &target,
+                )?);
+            }
+            for route in objects.iter() {
+                possible_routes.remove(&route.name_any());
+            }
+        } else if !ingress.redirect_from.is_empty() {
+            let redirect = generate_redirect_ingress(
+                app,
+                zone,
+                object_meta.clone(),
+                &ingress.redirect_from,
+                &target,
+            )?;
+            possible_ingresses.remove(&redirect.name_any());
+            objects.push(to_dynamic_object(redirect)?);
+        }
+        operations.extend(
+            objects
+                .into_iter()
+                .map(|object| Operation::CreateOrUpdate(Arc::new(object))),
+        );
+    }
+
+    for ingress_name in possible_ingresses {
+        let delete_ingress = Ingress {
+            metadata: ObjectMeta {
+                name: Some(ingress_name),
+                namespace: object_meta.namespace.clone(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let dynamic_object = to_dynamic_object(delete_ingress)?;
+        operations.push(Operation::DeleteIfExists(Arc::new(dynamic_object)));
+    }
+    for route_name in possible_routes {
+        let mut delete_route = DynamicObject::new(&route_name, &http_route_resource());
+        delete_route.metadata.namespace = object_meta.namespace.clone();
+        operations.push(Operation::DeleteIfExists(Arc::new(delete_route)));
+    }
+
+    Ok(operations)
+}
+
+fn redirect_name(app: &Application, zone: &str) -> String {
+    format!("{}-{}-redirect", app.name_any(), zone)
+}
+
+fn https_redirect_name(app: &Application, zone: &str) -> String {
+    format!("{}-{}-https-redirect", app.name_any(), zone)
+}
+
+fn zone_metadata(mut object_meta: ObjectMeta, zone: &IngressZone, name: String) -> ObjectMeta {
+    object_meta.name = Some(name);
+    object_meta
+        .labels
+        .get_or_insert_with(BTreeMap::new)
+        .insert(INGRESS_ZONE_LABEL.to_string(), zone.name_any());
+    if !zone.spec.annotations.is_empty() {
+        object_meta
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(zone.spec.annotations.clone());
+    }
+    object_meta
+}
+
+fn generate_redirect_ingress(
+    app: &Application,
+    zone: &IngressZone,
+    object_meta: ObjectMeta,
+    hosts: &[String],
+    target: &str,
+) -> Result<Ingress> {
+    let mut object_meta = zone_metadata(object_meta, zone, redirect_name(app, &zone.name_any()));
+    let annotations = object_meta.annotations.get_or_insert_with(BTreeMap::new);
+    let scheme = match zone.spec.tls {
+        Some(_) => "https",
+        None => "http",
+    };
+    match zone.spec.ingress_controller {
+        Some(IngressController::Nginx) => annotations.insert(
+            "nginx.ingress.kubernetes.io/permanent-redirect".to_string(),
+            format!("{}://{}$request_uri", scheme, target),
+        ),
+        None => bail!(
+            "ingress zone {} has no ingressController, redirects are not supported",
+            zone.name_any()
+        ),
+    };
+    let tls = zone.spec.tls.as_ref().map(|zone_tls| {
+        annotations.insert(
+            "cert-manager.io/cluster-issuer".to_string(),
+            zone_tls.cluster_issuer.clone().unwrap_or_default(),
+        );
+        vec![IngressTLS {
+            hosts: Some(hosts.to_vec()),
+            secret_name: Some(tls_secret_name(hosts)),
+        }]
+    });
+
+    // The backend is never used, but every rule needs one
+    let paths = vec![HTTPIngressPath {
+        backend: IngressBackend {
+            resource: None,
+            service: Some(IngressServiceBackend {
+                name: app.name_any(),
+                port: Some(ServiceBackendPort {
+                    name: Some("http".to_string()),
+                    number: None,
+                }),
+            }),
+        },
+        path: Some("/".to_string()),
+        path_type: "Prefix".to_string(),
+    }];
+    Ok(Ingress {
+        metadata: object_meta,
+        spec: Some(IngressSpec {
+            ingress_class_name: zone.spec.ingress_class.clone(),
+            rules: Some(
+                hosts
+                    .iter()
+                    .map(|host| IngressRule {
+                        host: Some(host.clone()),
+                        http: Some(HTTPIngressRuleValue {
+                            paths: paths.clone(),
+                        }),
+                    })
+                    .collect(),
+            ),
+            tls,
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+fn generate_redirect_route(
+    app: &Application,
+    zone: &IngressZone,
+    object_meta: ObjectMeta,
+    hosts: &[String],
+    target: &str,
+) -> Result<DynamicObject> {
+    let gateway = zone
+        .spec
+        .gateway
+        .as_ref()
+        .ok_or_else(|| anyhow!("Ingress zone has no gateway"))?;
+    let name = redirect_name(app, &zone.name_any());
+    let mut route = DynamicObject::new(&name, &http_route_resource()).data(json!({
+        "spec": {
+            "parentRefs": [parent_ref(gateway, gateway.section_name.as_ref())],
+            "hostnames": hosts,
+            "rules": [{
+                "filters": [{
+                    "type": "RequestRedirect",
+                    "requestRedirect": {"hostname": target, "statusCode": 301},
+                }],
+            }],
+        },
+    }));
+    route.metadata = zone_metadata(object_meta, zone, name);
+    Ok(route)
+}
+
+fn generate_https_redirect_route(
+    app: &Application,
+    zone: &IngressZone,
+    object_meta: ObjectMeta,
+    target: &str,
+) -> Result<DynamicObject> {
+    let gateway = zone
+        .spec
+        .gateway
+        .as_ref()
+        .ok_or_else(|| anyhow!("Ingress zone has no gateway"))?;
+    let http_section_name = gateway.http_section_name.as_ref().ok_or_else(|| {
+        anyhow!(
+            "gateway zone {} has no httpSectionName, httpsRedirect is not supported",
+            zone.name_any()
+        )
+    })?;
+    if gateway.section_name.is_none() {
+        bail!(
+            "gateway zone {} has no sectionName, httpsRedirect is not supported",
+            zone.name_any()
+        );
+    }
+    let name = https_redirect_name(app, &zone.name_any());
+    let mut route = DynamicObject::new(&name, &http_route_resource()).data(json!({
+        "spec": {
+            "parentRefs": [parent_ref(gateway, Some(http_section_name))],
+            "hostnames": [target],
+            "rules": [{
+                "filters": [{
+                    "type": "RequestRedirect",
+                    "requestRedirect": {"scheme": "https", "statusCode": 301},
+                }],
+            }],
+        },
+    }));
+    route.metadata = zone_metadata(object_meta, zone, name);
+    Ok(route)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zones(gateway: serde_json::Value) -> HashMap<String, Arc<IngressZone>> {
+        let zone: IngressZone = serde_json::from_value(json!({
+            "apiVersion": "yakup.ibidem.no/v1",
+            "kind": "IngressZone",
+            "metadata": {"name": "public"},
+            "spec": {"host": "{appname}.example.com", "gateway": gateway},
+        }))
+        .unwrap();
+        HashMap::from([("public".to_string(), Arc::new(zone))])
+    }
+
+    fn process_redirect(
+        zones: &HashMap<String, Arc<IngressZone>>,
+        https_redirect: bool,
+    ) -> Result<Vec<Operation>> {
+        let app: Application = serde_json::from_value(json!({
+            "apiVersion": "yakup.ibidem.no/v1",
+            "kind": "Application",
+            "metadata": {"name": "test-app", "namespace": "default"},
+            "spec": {
+                "image": "busybox:latest",
+                "ports": {"http": {"port": 8080, "ingress": [
+                    {"zone": "public", "httpsRedirect": https_redirect},
+                ]}},
+            },
+        }))
+        .unwrap();
+        let host_variables = HostVariables {
+            app_name: "test-app".to_string(),
+            namespace: "default".to_string(),
+            team: None,
+            labels: BTreeMap::new(),
+        };
+        process(
+            &Arc::new(app),
+            zones,
+            &[],
+            ObjectMeta::default(),
+            &host_variables,
+            true,
+        )
+    }
+
+    #[test]
+    fn https_redirect_requires_both_listeners() {
+        let both = zones(json!({"name": "gw", "sectionName": "https", "httpSectionName": "http"}));
+        assert!(process_redirect(&both, true).is_ok());
+
+        let no_section = zones(json!({"name": "gw", "httpSectionName": "http"}));
+        let err = process_redirect(&no_section, true).unwrap_err();
+        assert!(err.to_string().contains("no sectionName"), "{err}");
+
+        let no_http_section = zones(json!({"name": "gw", "sectionName": "https"}));
+        assert!(process_redirect(&no_http_section, true).is_err());
+    }
+
+    #[test]
+    fn disabled_https_redirect_is_rejected_in_gateway_zones() {
+        let both = zones(json!({"name": "gw", "sectionName": "https", "httpSectionName": "http"}));
+        let err = process_redirect(&both, false).unwrap_err();
+        assert!(err.to_string().contains("httpsRedirect: false"), "{err}");
+    }
+}
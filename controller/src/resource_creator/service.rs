@@ -1,54 +1,149 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use k8s_openapi::api::core::v1::{Service, ServicePort, ServiceSpec};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
 use tracing::instrument;
 
 use api::application::v1::Application;
+use api::application::{HttpPort, ServiceType};
 
 use crate::models::Operation;
-use crate::resource_creator::to_dynamic_object;
+use crate::resource_creator::{
+    headless_service_name, is_stateful, to_dynamic_object, ClusterState,
+};
 
-#[instrument(skip(app, object_meta), fields(trace_id))]
+#[instrument(skip(app, object_meta, cluster_state), fields(trace_id))]
 pub(crate) fn process(
     app: &Arc<Application>,
     object_meta: ObjectMeta,
     labels: BTreeMap<String, String>,
+    cluster_state: &ClusterState,
 ) -> Result<Vec<Operation>> {
+    let mut operations = generate_service(app, object_meta.clone(), &labels, cluster_state)?;
+    operations.push(generate_headless_service(app, object_meta, &labels)?);
+    Ok(operations)
+}
+
+/// The clusterIP of a Service can't be changed, so switching to or from a headless Service
+/// deletes the existing Service before creating the new one.
+fn generate_service(
+    app: &Arc<Application>,
+    object_meta: ObjectMeta,
+    labels: &BTreeMap<String, String>,
+    cluster_state: &ClusterState,
+) -> Result<Vec<Operation>> {
+    let delete = Operation::DeleteIfExists(Arc::new(to_dynamic_object(Service {
+        metadata: object_meta.clone(),
+        ..Default::default()
+    })?));
     let ports = generate_ports(app.clone());
     if ports.is_none() || ports.as_ref().unwrap().is_empty() {
-        return Ok(vec![Operation::DeleteIfExists(Arc::new(
-            to_dynamic_object(Service {
+        return Ok(vec![delete]);
+    }
+    let options = app.spec.service.clone().unwrap_or_default();
+    let service_type = options.service_type.unwrap_or_default();
+    if options.external_traffic_policy.is_some()
+        && !matches!(
+            service_type,
+            ServiceType::LoadBalancer | ServiceType::NodePort
+        )
+    {
+        bail!(
+            "externalTrafficPolicy is only valid for LoadBalancer and NodePort services, not {}",
+            service_type
+        );
+    }
+    let mut object_meta = object_meta;
+    if !options.annotations.is_empty() {
+        object_meta
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(options.annotations.clone());
+    }
+    let (type_, cluster_ip) = match service_type {
+        ServiceType::Headless => (ServiceType::ClusterIP, Some("None".to_string())),
+        service_type => (service_type, None),
+    };
+    let switching_headless = cluster_state
+        .service_cluster_ip
+        .as_ref()
+        .is_some_and(|existing| (existing == "None") != cluster_ip.is_some());
+    let svc = Service {
+        metadata: object_meta,
+        spec: Some(ServiceSpec {
+            type_: Some(type_.to_string()),
+            cluster_ip,
+            selector: Some(labels.clone()),
+            ports,
+            session_affinity: options.session_affinity.map(|s| s.to_string()),
+            external_traffic_policy: options.external_traffic_policy.map(|p| p.to_string()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let create = Operation::CreateOrUpdate(Arc::new(to_dynamic_object(svc)?));
+    if switching_headless {
+        Ok(vec![delete, create])
+    } else {
+        Ok(vec![create])
+    }
+}
+
+fn generate_headless_service(
+    app: &Arc<Application>,
+    mut object_meta: ObjectMeta,
+    labels: &BTreeMap<String, String>,
+) -> Result<Operation> {
+    object_meta.name = Some(headless_service_name(app));
+    if !is_stateful(app) {
+        return Ok(Operation::DeleteIfExists(Arc::new(to_dynamic_object(
+            Service {
                 metadata: object_meta,
                 ..Default::default()
-            })?,
-        ))]);
+            },
+        )?)));
     }
     let svc = Service {
         metadata: object_meta,
         spec: Some(ServiceSpec {
+            cluster_ip: Some("None".to_string()),
+            publish_not_ready_addresses: Some(true),
             selector: Some(labels.clone()),
-            ports,
+            ports: generate_ports(app.clone()),
             ..Default::default()
         }),
         ..Default::default()
     };
 
-    Ok(vec![Operation::CreateOrUpdate(Arc::new(
-        to_dynamic_object(svc)?,
-    ))])
+    Ok(Operation::CreateOrUpdate(Arc::new(to_dynamic_object(svc)?)))
+}
+
+/// The port number of the named service port, if the application has it.
+pub(crate) fn service_port_number(app: &Application, name: &str) -> Option<i32> {
+    let ports = app.spec.ports.as_ref()?;
+    match name {
+        "http" => ports.http.as_ref().map(http_service_port),
+        "tcp" => ports.tcp.as_ref().map(|tcp_port| tcp_port.port as i32),
+        "udp" => ports.udp.as_ref().map(|udp_port| udp_port.port as i32),
+        _ => None,
+    }
+}
+
+fn http_service_port(http_port: &HttpPort) -> i32 {
+    http_port.service_port.unwrap_or(80) as i32
 }
 
 fn generate_ports(app: Arc<Application>) -> Option<Vec<ServicePort>> {
     let mut service_ports = Vec::new();
     if let Some(ports) = &app.spec.ports {
-        if let Some(_http_port) = &ports.http {
+        if let Some(http_port) = &ports.http {
             service_ports.push(ServicePort {
                 name: Some("http".to_string()),
-                port: 80,
+                port: http_service_port(http_port),
                 target_port: Some(IntOrString::String("http".to_string())),
                 ..Default::default()
             });
@@ -62,6 +157,16 @@ fn generate_ports(app: Arc<Application>) -> Option<Vec<ServicePort>> {
                 ..Default::default()
             });
         }
+
+        if let Some(udp_port) = &ports.udp {
+            service_ports.push(ServicePort {
+                name: Some("udp".to_string()),
+                port: udp_port.port as i32,
+                protocol: Some("UDP".to_string()),
+                target_port: Some(IntOrString::String("udp".to_string())),
+                ..Default::default()
+            });
+        }
     }
     if service_ports.is_empty() {
         None
@@ -1,18 +1,52 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use k8s_openapi::api::core::v1::ServiceAccount;
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::ResourceExt;
 use tracing::instrument;
 
+use api::application::v1::Application;
+
 use crate::models::Operation;
 use crate::resource_creator::to_dynamic_object;
 
-#[instrument(skip(object_meta), fields(trace_id))]
-pub(crate) fn process(object_meta: ObjectMeta) -> Result<Vec<Operation>> {
+/// Applications run as a ServiceAccount of their own, unless an existing one is named.
+#[instrument(skip(app, object_meta), fields(trace_id))]
+pub(crate) fn process(app: &Arc<Application>, object_meta: ObjectMeta) -> Result<Vec<Operation>> {
+    let options = app.spec.service_account.clone().unwrap_or_default();
+    if let Some(name) = &options.name {
+        if !options.annotations.is_empty() {
+            bail!("serviceAccount annotations can not be set on an existing ServiceAccount");
+        }
+        // Never delete an existing ServiceAccount that happens to have the application name
+        if *name == app.name_any() {
+            return Ok(vec![]);
+        }
+        let sa = ServiceAccount {
+            metadata: ObjectMeta {
+                name: object_meta.name,
+                namespace: object_meta.namespace,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        return Ok(vec![Operation::DeleteIfExists(Arc::new(
+            to_dynamic_object(sa)?,
+        ))]);
+    }
+
+    let mut metadata = object_meta;
+    if !options.annotations.is_empty() {
+        metadata
+            .annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(options.annotations.clone());
+    }
     let sa = ServiceAccount {
-        metadata: object_meta,
-        automount_service_account_token: Some(true),
+        metadata,
+        automount_service_account_token: Some(options.automount_token.unwrap_or(true)),
         ..Default::default()
     };
 
@@ -20,3 +54,12 @@ pub(crate) fn process(object_meta: ObjectMeta) -> Result<Vec<Operation>> {
         to_dynamic_object(sa)?,
     ))])
 }
+
+/// The name of the ServiceAccount the application runs as.
+pub(crate) fn service_account_name(app: &Application) -> String {
+    app.spec
+        .service_account
+        .as_ref()
+        .and_then(|options| options.name.clone())
+        .unwrap_or(app.name_any())
+}
@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Result};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+use k8s_openapi::chrono::Utc;
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client, Resource, ResourceExt};
+use serde_json::json;
+use tracing::instrument;
+
+use api::application::v1::Application;
+
+/// Build a condition for the object, observed at the current generation.
+pub fn condition<K: Resource>(
+    obj: &K,
+    type_: &str,
+    ok: bool,
+    reason: &str,
+    message: &str,
+) -> Condition {
+    Condition {
+        type_: type_.to_string(),
+        status: if ok { "True" } else { "False" }.to_string(),
+        reason: reason.to_string(),
+        message: message.to_string(),
+        observed_generation: obj.meta().generation,
+        last_transition_time: Time(Utc::now()),
+    }
+}
+
+/// Add or replace the conditions of the same type in the list.
+/// The transition time is kept when the status of a condition is unchanged.
+pub fn set_conditions(existing: &[Condition], conditions: Vec<Condition>) -> Vec<Condition> {
+    let mut result: Vec<Condition> = existing
+        .iter()
+        .filter(|c| !conditions.iter().any(|n| n.type_ == c.type_))
+        .cloned()
+        .collect();
+    for mut condition in conditions {
+        if let Some(old) = existing.iter().find(|c| c.type_ == condition.type_) {
+            if old.status == condition.status {
+                condition.last_transition_time = old.last_transition_time.clone();
+            }
+        }
+        result.push(condition);
+    }
+    result.sort_by(|a, b| a.type_.cmp(&b.type_));
+    result
+}
+
+/// Update the conditions in the application status, if anything changed.
+#[instrument(skip(client, app, conditions), fields(trace_id))]
+pub async fn update_conditions(
+    client: &Client,
+    app: &Application,
+    conditions: Vec<Condition>,
+) -> Result<()> {
+    let existing = app
+        .status
+        .as_ref()
+        .map(|status| status.conditions.clone())
+        .unwrap_or_default();
+    let updated = set_conditions(&existing, conditions);
+    if updated == existing {
+        return Ok(());
+    }
+    let namespace = app.namespace().unwrap_or("default".to_string());
+    Api::<Application>::namespaced(client.clone(), &namespace)
+        .patch_status(
+            &app.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({"status": {"conditions": updated}})),
+        )
+        .await
+        .map_err(|e| anyhow!(e).context("updating application status"))?;
+    Ok(())
+}
+
+/// Record the image pinned to a digest in the application status, if it changed.
+#[instrument(skip(client, app), fields(trace_id))]
+pub async fn update_resolved_image(
+    client: &Client,
+    app: &Application,
+    resolved_image: Option<String>,
+) -> Result<()> {
+    let generation = resolved_image.as_ref().and(app.metadata.generation);
+    let status = app.status.clone().unwrap_or_default();
+    if status.resolved_image == resolved_image && status.resolved_image_generation == generation {
+        return Ok(());
+    }
+    let namespace = app.namespace().unwrap_or("default".to_string());
+    Api::<Application>::namespaced(client.clone(), &namespace)
+        .patch_status(
+            &app.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({"status": {
+                "resolvedImage": resolved_image,
+                "resolvedImageGeneration": generation,
+            }})),
+        )
+        .await
+        .map_err(|e| anyhow!(e).context("updating application status"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn condition_at(type_: &str, status: &str, timestamp: i64) -> Condition {
+        Condition {
+            type_: type_.to_string(),
+            status: status.to_string(),
+            reason: "Test".to_string(),
+            message: String::new(),
+            observed_generation: None,
+            last_transition_time: Time(Utc.timestamp_opt(timestamp, 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn set_conditions_keeps_transition_time_for_unchanged_status() {
+        let existing = vec![
+            condition_at("IngressAllowed", "True", 1),
+            condition_at("Other", "True", 2),
+        ];
+        let updated = set_conditions(
+            &existing,
+            vec![
+                condition_at("IngressAllowed", "True", 10),
+                condition_at("New", "False", 11),
+            ],
+        );
+        assert_eq!(
+            updated,
+            vec![
+                condition_at("IngressAllowed", "True", 1),
+                condition_at("New", "False", 11),
+                condition_at("Other", "True", 2),
+            ]
+        );
+
+        let updated = set_conditions(&existing, vec![condition_at("IngressAllowed", "False", 12)]);
+        assert_eq!(updated[0], condition_at("IngressAllowed", "False", 12));
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::apps::v1::{Deployment, StatefulSet};
+use kube::{Api, Client, ResourceExt};
+use tracing::instrument;
+
+use api::application::v1::Application;
+
+use crate::resource_creator::ExistingStatefulSet;
+
+/// The existing workloads of an application.
+#[derive(Debug, Default)]
+pub struct Workloads {
+    /// The kinds of workloads that exist, and whether all their replicas are ready.
+    pub ready: BTreeMap<String, bool>,
+
+    /// The fields of the existing StatefulSet that can't be changed.
+    pub stateful_set: Option<ExistingStatefulSet>,
+}
+
+/// Find the workloads of the application.
+/// Used to keep the old workload running while switching between Deployment and StatefulSet,
+/// and to avoid changes to a StatefulSet that the API server rejects.
+#[instrument(skip(client, app), fields(trace_id))]
+pub async fn find_workloads(client: &Client, app: &Application) -> Result<Workloads> {
+    let namespace = app.namespace().unwrap_or("default".to_string());
+    let mut workloads = Workloads::default();
+    if let Some(deployment) = Api::<Deployment>::namespaced(client.clone(), &namespace)
+        .get_opt(&app.name_any())
+        .await
+        .map_err(|e| anyhow!(e).context("getting deployment"))?
+    {
+        workloads
+            .ready
+            .insert("Deployment".to_string(), deployment_ready(&deployment));
+    }
+    if let Some(stateful_set) = Api::<StatefulSet>::namespaced(client.clone(), &namespace)
+        .get_opt(&app.name_any())
+        .await
+        .map_err(|e| anyhow!(e).context("getting statefulset"))?
+    {
+        workloads
+            .ready
+            .insert("StatefulSet".to_string(), stateful_set_ready(&stateful_set));
+        let spec = stateful_set.spec.unwrap_or_default();
+        workloads.stateful_set = Some(ExistingStatefulSet {
+            service_name: spec.service_name,
+            volume_claim_templates: spec.volume_claim_templates.unwrap_or_default(),
+        });
+    }
+    Ok(workloads)
+}
+
+fn deployment_ready(deployment: &Deployment) -> bool {
+    let spec = deployment.spec.clone().unwrap_or_default();
+    let status = deployment.status.clone().unwrap_or_default();
+    let replicas = spec.replicas.unwrap_or(1);
+    status.observed_generation >= deployment.metadata.generation
+        && status.updated_replicas.unwrap_or_default() >= replicas
+        && status.available_replicas.unwrap_or_default() >= replicas
+}
+
+fn stateful_set_ready(stateful_set: &StatefulSet) -> bool {
+    let spec = stateful_set.spec.clone().unwrap_or_default();
+    let status = stateful_set.status.clone().unwrap_or_default();
+    let replicas = spec.replicas.unwrap_or(1);
+    status.observed_generation >= stateful_set.metadata.generation
+        && status.current_revision == status.update_revision
+        && status.ready_replicas.unwrap_or_default() >= replicas
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::{DeploymentSpec, DeploymentStatus};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    use super::*;
+
+    fn deployment(generation: i64, observed: i64, available: i32) -> Deployment {
+        Deployment {
+            metadata: ObjectMeta {
+                generation: Some(generation),
+                ..Default::default()
+            },
+            spec: Some(DeploymentSpec {
+                replicas: Some(2),
+                ..Default::default()
+            }),
+            status: Some(DeploymentStatus {
+                observed_generation: Some(observed),
+                updated_replicas: Some(2),
+                available_replicas: Some(available),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn deployment_ready_requires_all_replicas_of_current_generation() {
+        assert!(deployment_ready(&deployment(2, 2, 2)));
+        assert!(!deployment_ready(&deployment(2, 2, 1)));
+        assert!(!deployment_ready(&deployment(3, 2, 2)));
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{LabelSelector, LabelSelectorRequirement};
+use kube::ResourceExt;
+
+use api::application::v1::Application;
+use api::ingress_zone::v1::IngressZone;
+
+use crate::resource_creator::ClusterState;
+
+/// Check if applications in the namespace may use the zone.
+pub fn namespace_allowed(
+    zone: &IngressZone,
+    namespace: &str,
+    namespace_labels: &BTreeMap<String, String>,
+) -> bool {
+    match &zone.spec.allowed_namespaces {
+        None => true,
+        Some(allowed) => {
+            allowed.names.iter().any(|name| name == namespace)
+                || allowed
+                    .selector
+                    .as_ref()
+                    .is_some_and(|selector| selector_matches(selector, namespace_labels))
+        }
+    }
+}
+
+/// The zones the application wants to be exposed in, but its namespace is not allowed to use.
+pub fn denied_zones(
+    app: &Application,
+    zones: &HashMap<String, Arc<IngressZone>>,
+    cluster_state: &ClusterState,
+) -> Vec<String> {
+    let namespace = app.namespace().unwrap_or("default".to_string());
+    let mut denied: Vec<String> = app
+        .spec
+        .ports
+        .iter()
+        .filter_map(|ports| ports.http.as_ref())
+        .flat_map(|http| http.ingress.iter())
+        .filter_map(|ingress| zones.get(&ingress.zone))
+        .filter(|zone| !namespace_allowed(zone, &namespace, &cluster_state.namespace_labels))
+        .map(|zone| zone.name_any())
+        .collect();
+    denied.sort();
+    denied.dedup();
+    denied
+}
+
+/// The names of the zones the application wants to be exposed in.
+pub fn referenced_zones(app: &Application) -> BTreeSet<String> {
+    app.spec
+        .ports
+        .iter()
+        .filter_map(|ports| ports.http.as_ref())
+        .flat_map(|http| http.ingress.iter())
+        .map(|ingress| ingress.zone.clone())
+        .collect()
+}
+
+fn selector_matches(selector: &LabelSelector, labels: &BTreeMap<String, String>) -> bool {
+    let labels_match = selector
+        .match_labels
+        .iter()
+        .flatten()
+        .all(|(key, value)| labels.get(key) == Some(value));
+    let expressions_match = selector
+        .match_expressions
+        .iter()
+        .flatten()
+        .all(|requirement| requirement_matches(requirement, labels));
+    labels_match && expressions_match
+}
+
+fn requirement_matches(
+    requirement: &LabelSelectorRequirement,
+    labels: &BTreeMap<String, String>,
+) -> bool {
+    let value = labels.get(&requirement.key);
+    let values = requirement.values.as_deref().unwrap_or_default();
+    match requirement.operator.as_str() {
+        "In" => value.is_some_and(|value| values.contains(value)),
+        "NotIn" => value.is_none_or(|value| !values.contains(value)),
+        "Exists" => value.is_some(),
+        "DoesNotExist" => value.is_none(),
+        _ => false,
+    }
+}
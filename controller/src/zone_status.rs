@@ -0,0 +1,364 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use k8s_openapi::api::core::v1::Namespace;
+use k8s_openapi::api::networking::v1::IngressClass;
+use kube::api::{DynamicObject, Patch, PatchParams};
+use kube::discovery::ApiResource;
+use kube::runtime::reflector::{ObjectRef, Store};
+use kube::{Api, Client, ResourceExt};
+use serde_json::json;
+use tracing::{instrument, warn};
+
+use api::application::v1::Application;
+use api::ingress_zone::v1::IngressZone;
+use api::ingress_zone::{IngressZoneStatus, ZoneConsumer};
+
+use crate::config::Config;
+use crate::host_template::HostTemplate;
+use crate::resource_creator::{zone_hosts, ClusterState};
+use crate::status;
+use crate::zone_access::{namespace_allowed, referenced_zones};
+
+/// Update the status of the zone with its consumers, the hosts handed out and the `Ready` condition.
+/// Consumers are found in the cached applications and namespaces of the controller.
+#[instrument(skip(client, zone, config, apps, namespaces), fields(trace_id))]
+pub async fn update_status(
+    client: &Client,
+    zone: &IngressZone,
+    config: &Config,
+    apps: &Store<Application>,
+    namespaces: &Store<Namespace>,
+) -> Result<()> {
+    let problems = find_problems(client, zone).await?;
+    let ready = if problems.is_empty() {
+        status::condition(zone, "Ready", true, "Ready", "")
+    } else {
+        status::condition(zone, "Ready", false, "NotReady", &problems.join("; "))
+    };
+
+    let (consumers, hosts) = find_consumers(zone, &apps.state(), namespaces, config);
+    let existing = zone
+        .status
+        .as_ref()
+        .map(|status| status.conditions.clone())
+        .unwrap_or_default();
+    let status = IngressZoneStatus {
+        conditions: status::set_conditions(&existing, vec![ready]),
+        consumer_count: consumers.len() as u32,
+        consumers,
+        hosts,
+    };
+
+    Api::<IngressZone>::all(client.clone())
+        .patch_status(
+            &zone.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(json!({ "status": status })),
+        )
+        .await
+        .map_err(|e| anyhow!(e).context("updating ingress zone status"))?;
+    Ok(())
+}
+
+/// Check that the host template is valid, and that the resources the zone refers to exist.
+async fn find_problems(client: &Client, zone: &IngressZone) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    if let Err(e) = HostTemplate::parse(&zone.spec.host) {
+        problems.push(e.to_string());
+    }
+    match &zone.spec.gateway {
+        Some(gateway) => {
+            if let Some(namespace) = &gateway.namespace {
+                let gateways = Api::<DynamicObject>::namespaced_with(
+                    client.clone(),
+                    namespace,
+                    &api_resource("gateway.networking.k8s.io", "v1", "Gateway", "gateways"),
+                );
+                if missing(gateways, &gateway.name).await? {
+                    problems.push(format!("Gateway {}/{} not found", namespace, gateway.name));
+                }
+            }
+        }
+        None => {
+            if let Some(ingress_class) = &zone.spec.ingress_class {
+                let ingress_classes = Api::<IngressClass>::all(client.clone());
+                let found = ingress_classes
+                    .get_opt(ingress_class)
+                    .await
+                    .map_err(|e| anyhow!(e).context("getting ingress class"))?;
+                if found.is_none() {
+                    problems.push(format!("IngressClass {} not found", ingress_class));
+                }
+            }
+            if let Some(issuer) = zone
+                .spec
+                .tls
+                .as_ref()
+                .and_then(|tls| tls.cluster_issuer.as_ref())
+            {
+                let issuers = Api::<DynamicObject>::all_with(
+                    client.clone(),
+                    &api_resource("cert-manager.io", "v1", "ClusterIssuer", "clusterissuers"),
+                );
+                if missing(issuers, issuer).await? {
+                    problems.push(format!("ClusterIssuer {} not found", issuer));
+                }
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// The applications exposed in the zone, and the hosts handed out to them.
+/// Applications in namespaces not allowed to use the zone are not consumers.
+fn find_consumers(
+    zone: &IngressZone,
+    apps: &[Arc<Application>],
+    namespaces: &Store<Namespace>,
+    config: &Config,
+) -> (Vec<ZoneConsumer>, Vec<String>) {
+    let mut consumers = Vec::new();
+    let mut hosts = Vec::new();
+    for (app, app_hosts) in consumer_hosts(zone, apps, namespaces, config) {
+        consumers.push(ZoneConsumer {
+            namespace: app.namespace().unwrap_or("default".to_string()),
+            name: app.name_any(),
+        });
+        hosts.extend(app_hosts);
+    }
+    consumers.sort();
+    hosts.sort();
+    hosts.dedup();
+    (consumers, hosts)
+}
+
+/// The hosts of the application that an earlier consumer of the same zone already has, by zone.
+/// The application created first keeps a host, so other applications can't take it over.
+pub fn host_conflicts(
+    app: &Application,
+    zones: &HashMap<String, Arc<IngressZone>>,
+    apps: &[Arc<Application>],
+    namespaces: &Store<Namespace>,
+    config: &Config,
+) -> BTreeMap<String, Vec<String>> {
+    let mut conflicts = BTreeMap::new();
+    for zone in referenced_zones(app)
+        .iter()
+        .filter_map(|name| zones.get(name))
+    {
+        let consumers = consumer_hosts(zone, apps, namespaces, config);
+        let Some((_, own_hosts)) = consumers.iter().find(|(other, _)| same_app(other, app)) else {
+            continue;
+        };
+        let mut taken: Vec<String> = consumers
+            .iter()
+            .filter(|(other, _)| !same_app(other, app) && claimed_first(other, app))
+            .flat_map(|(_, hosts)| hosts.iter())
+            .filter(|host| own_hosts.contains(host))
+            .cloned()
+            .collect();
+        if !taken.is_empty() {
+            taken.sort();
+            taken.dedup();
+            conflicts.insert(zone.name_any(), taken);
+        }
+    }
+    conflicts
+}
+
+/// The applications exposed in the zone from namespaces allowed to use it, with their hosts.
+fn consumer_hosts<'a>(
+    zone: &IngressZone,
+    apps: &'a [Arc<Application>],
+    namespaces: &Store<Namespace>,
+    config: &Config,
+) -> Vec<(&'a Arc<Application>, Vec<String>)> {
+    let mut consumers = Vec::new();
+    for app in apps.iter() {
+        let namespace = app.namespace().unwrap_or("default".to_string());
+        let cluster_state = ClusterState {
+            namespace_labels: namespaces
+                .get(&ObjectRef::new(&namespace))
+                .map(|ns| ns.labels().clone())
+                .unwrap_or_default(),
+            ..Default::default()
+        };
+        if !namespace_allowed(zone, &namespace, &cluster_state.namespace_labels) {
+            continue;
+        }
+        let ingresses: Vec<_> = app
+            .spec
+            .ports
+            .iter()
+            .filter_map(|ports| ports.http.as_ref())
+            .flat_map(|http| http.ingress.iter())
+            .filter(|ingress| ingress.zone == zone.name_any())
+            .collect();
+        if ingresses.is_empty() {
+            continue;
+        }
+        let mut hosts = Vec::new();
+        for ingress in ingresses {
+            match zone_hosts(app, zone, ingress, config, &cluster_state) {
+                Ok(app_hosts) => hosts.extend(app_hosts),
+                Err(e) => warn!("Unable to generate hosts for {}: {:?}", app.name_any(), e),
+            }
+        }
+        consumers.push((app, hosts));
+    }
+    consumers
+}
+
+fn same_app(a: &Application, b: &Application) -> bool {
+    a.namespace() == b.namespace() && a.name_any() == b.name_any()
+}
+
+/// Whether application `a` was created before `b`, ordered by name when created at the same time.
+fn claimed_first(a: &Application, b: &Application) -> bool {
+    let key = |app: &Application| {
+        (
+            app.metadata.creation_timestamp.clone(),
+            app.namespace(),
+            app.name_any(),
+        )
+    };
+    key(a) < key(b)
+}
+
+fn api_resource(group: &str, version: &str, kind: &str, plural: &str) -> ApiResource {
+    ApiResource {
+        group: group.to_string(),
+        version: version.to_string(),
+        api_version: format!("{}/{}", group, version),
+        kind: kind.to_string(),
+        plural: plural.to_string(),
+    }
+}
+
+/// A missing CRD is reported the same way as a missing object.
+async fn missing(api: Api<DynamicObject>, name: &str) -> Result<bool> {
+    api.get_opt(name)
+        .await
+        .map(|found| found.is_none())
+        .map_err(|e| anyhow!(e).context(format!("getting {}", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use kube::runtime::{reflector, watcher};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    use super::*;
+
+    fn app(namespace: &str, name: &str, zone: &str) -> Arc<Application> {
+        Arc::new(
+            serde_json::from_value(json!({
+                "apiVersion": "yakup.ibidem.no/v1",
+                "kind": "Application",
+                "metadata": {"name": name, "namespace": namespace},
+                "spec": {
+                    "image": "busybox:latest",
+                    "ports": {"http": {"port": 8080, "ingress": [{"zone": zone}]}},
+                },
+            }))
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn find_consumers_in_allowed_namespaces() {
+        let zone: IngressZone = serde_json::from_value(json!({
+            "apiVersion": "yakup.ibidem.no/v1",
+            "kind": "IngressZone",
+            "metadata": {"name": "public"},
+            "spec": {
+                "host": "{appname}.{namespace}.example.com",
+                "allowedNamespaces": {"selector": {"matchLabels": {"exposed": "true"}}},
+            },
+        }))
+        .unwrap();
+        let (namespaces, mut writer) = reflector::store::<Namespace>();
+        for (name, exposed) in [("web", "true"), ("internal", "false")] {
+            let namespace: Namespace = serde_json::from_value(json!({
+                "metadata": {"name": name, "labels": {"exposed": exposed}},
+            }))
+            .unwrap();
+            writer.apply_watcher_event(&watcher::Event::Apply(namespace));
+        }
+        let apps = vec![
+            app("web", "shop", "public"),
+            app("web", "blog", "public"),
+            app("web", "admin", "private"),
+            app("internal", "billing", "public"),
+        ];
+
+        let (consumers, hosts) = find_consumers(&zone, &apps, &namespaces, &Config::default());
+        assert_eq!(
+            consumers,
+            vec![
+                ZoneConsumer {
+                    namespace: "web".to_string(),
+                    name: "blog".to_string(),
+                },
+                ZoneConsumer {
+                    namespace: "web".to_string(),
+                    name: "shop".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            hosts,
+            vec![
+                "blog.web.example.com".to_string(),
+                "shop.web.example.com".to_string(),
+            ]
+        );
+    }
+
+    fn vanity_app(namespace: &str, name: &str, created: &str) -> Arc<Application> {
+        Arc::new(
+            serde_json::from_value(json!({
+                "apiVersion": "yakup.ibidem.no/v1",
+                "kind": "Application",
+                "metadata": {"name": name, "namespace": namespace, "creationTimestamp": created},
+                "spec": {
+                    "image": "busybox:latest",
+                    "ports": {"http": {"port": 8080, "ingress": [
+                        {"zone": "public", "hosts": ["www.example.org"]},
+                    ]}},
+                },
+            }))
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn host_conflicts_keep_host_with_first_application() {
+        let zone: IngressZone = serde_json::from_value(json!({
+            "apiVersion": "yakup.ibidem.no/v1",
+            "kind": "IngressZone",
+            "metadata": {"name": "public"},
+            "spec": {"host": "{appname}.{namespace}.example.com", "allowedDomains": ["example.org"]},
+        }))
+        .unwrap();
+        let zones = HashMap::from([("public".to_string(), Arc::new(zone))]);
+        let (namespaces, _) = reflector::store::<Namespace>();
+        let owner = vanity_app("web", "shop", "2024-01-01T00:00:00Z");
+        let hijacker = vanity_app("other", "shop", "2024-02-01T00:00:00Z");
+        let apps = vec![
+            owner.clone(),
+            hijacker.clone(),
+            app("web", "blog", "public"),
+        ];
+
+        let config = Config::default();
+        assert!(host_conflicts(&owner, &zones, &apps, &namespaces, &config).is_empty());
+        assert_eq!(
+            host_conflicts(&hijacker, &zones, &apps, &namespaces, &config),
+            BTreeMap::from([("public".to_string(), vec!["www.example.org".to_string()])])
+        );
+    }
+}
@@ -11,15 +11,32 @@ use test_generator::test_resources;
 use api::application::v1::{Application, ApplicationSpec};
 use api::ingress_zone::v1::{IngressZone, IngressZoneSpec};
 use api::ingress_zone::IngressZoneTLS;
+use controller::config::Config;
 use controller::models::Operation;
-use controller::resource_creator::process;
+use controller::resource_creator::{process, ClusterState};
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+use kube::ResourceExt;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct TestCase {
     name: String,
+    #[serde(default)]
+    config: Config,
+    #[serde(default)]
+    cluster_state: ClusterState,
+    #[serde(default)]
+    zones: Vec<IngressZone>,
+    #[serde(default)]
+    app_metadata: ObjectMeta,
     app_spec: ApplicationSpec,
+    #[serde(default)]
     operations: Vec<Operation>,
+    /// Text in the error processing must fail with, instead of returning operations.
+    #[serde(default)]
+    error: Option<String>,
+    /// JSON pointers into the operations that must not be set, such as `/0/object/metadata/annotations`.
+    #[serde(default)]
+    absent: Vec<String>,
 }
 
 #[test_resources("controller/tests/testdata/*.yaml")]
@@ -29,7 +46,7 @@ fn test_process(resource: PathBuf) {
     let f = File::open(full_path).expect("Could not open file.");
     let case: TestCase = serde_yaml::from_reader(f).expect("Could not read test case.");
 
-    let zones = HashMap::from([
+    let mut zones = HashMap::from([
         (
             "public".to_string(),
             Arc::new(IngressZone {
@@ -39,11 +56,20 @@ fn test_process(resource: PathBuf) {
                 },
                 spec: IngressZoneSpec {
                     host: "{appname}.example.com".to_string(),
+                    allowed_domains: vec![],
+                    auth: None,
+                    allowed_namespaces: None,
+                    annotations: Default::default(),
+                    allowed_annotations: vec![],
+                    ingress_controller: None,
                     ingress_class: None,
                     tls: Some(IngressZoneTLS {
                         cluster_issuer: Some("letsencrypt-staging".to_string()),
                     }),
+                    controller_namespace: Some("ingress-public".to_string()),
+                    gateway: None,
                 },
+                status: None,
             }),
         ),
         (
@@ -55,15 +81,39 @@ fn test_process(resource: PathBuf) {
                 },
                 spec: IngressZoneSpec {
                     host: "{appname}.private.example.com".to_string(),
+                    allowed_domains: vec![],
+                    auth: None,
+                    allowed_namespaces: None,
+                    annotations: Default::default(),
+                    allowed_annotations: vec![],
+                    ingress_controller: None,
                     ingress_class: Some("private".to_string()),
                     tls: None,
+                    controller_namespace: None,
+                    gateway: None,
                 },
+                status: None,
             }),
         ),
     ]);
+    for zone in case.zones {
+        zones.insert(zone.name_any(), Arc::new(zone));
+    }
 
-    let app = Application::new("test-app", case.app_spec);
-    let operations = process(Arc::new(app), &zones).unwrap();
+    let mut app = Application::new("test-app", case.app_spec);
+    app.metadata.labels = case.app_metadata.labels;
+    app.metadata.annotations = case.app_metadata.annotations;
+    let result = process(Arc::new(app), &zones, &case.config, &case.cluster_state);
+    if let Some(error) = case.error {
+        let err = result.expect_err("Processing should fail.");
+        assert!(
+            format!("{:#}", err).contains(&error),
+            "unexpected error: {:#}",
+            err
+        );
+        return;
+    }
+    let operations = result.unwrap();
 
     for (operation, expected_operation) in operations.iter().zip(case.operations.iter()) {
         println!(
@@ -79,4 +129,13 @@ fn test_process(resource: PathBuf) {
             .expect("Could not serialize expected operation.");
         assert_json_include!(actual: actual, expected: expected);
     }
+    let actual = serde_json::to_value(&operations).expect("Could not serialize operations.");
+    for pointer in case.absent.iter() {
+        assert!(
+            actual.pointer(pointer).is_none(),
+            "{} is set: {}",
+            pointer,
+            actual.pointer(pointer).unwrap()
+        );
+    }
 }